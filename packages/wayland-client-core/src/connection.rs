@@ -1,49 +1,127 @@
-use std::os::fd::{FromRawFd, OwnedFd, RawFd};
-use std::os::unix::net::UnixStream;
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, IoSlice, IoSliceMut};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::{AncillaryData, SocketAncillary, UnixStream};
 use std::path::PathBuf;
+use std::rc::Rc;
 use std::result::Result;
 
-use crate::proxy;
+use crate::object_map::{Object, ObjectMap};
+use crate::proxy::{self, EventSignature};
+use crate::wire::{self, Argument, HEADER_SIZE, Value};
+
+// The limits libwayland puts on a single `sendmsg`.
+const MAX_BUFFER_SIZE: usize = 4096;
+const MAX_FDS: usize = 28;
 
 pub struct Connection {
+    state: Rc<State>,
+}
+
+// Shared between the connection and every proxy created from it.
+pub(crate) struct State {
     stream: UnixStream,
+    objects: RefCell<ObjectMap>,
+    outgoing: RefCell<Outgoing>,
+    incoming: RefCell<Incoming>,
+    // Each a `Handler<D>` for the state type it was registered with.
+    handlers: RefCell<HashMap<u32, Box<dyn Any>>>,
 }
 
-enum InternalEvent<E: Event> {
-    DisplayError,
-    DisplayDeleteId,
-    Application(E),
+pub(crate) type Handler<D> = Box<dyn FnMut(&mut D, u16, Vec<Value>)>;
+
+#[derive(Default)]
+struct Outgoing {
+    bytes: Vec<u8>,
+    fds: Vec<OwnedFd>,
+    // A failed automatic flush, reported by the next call to `Connection::flush`.
+    error: Option<io::Error>,
 }
 
-impl<E: Event> Event for InternalEvent<E> {
-    fn from_bytes(bytes: &[u8]) -> Self {
-        if bytes[0] == 0 {
-            Self::DisplayError
-        } else if bytes[0] == 1 {
-            Self::DisplayDeleteId
-        } else {
-            Self::Application(E::from_bytes(bytes))
-        }
-    }
+#[derive(Default)]
+struct Incoming {
+    bytes: Vec<u8>,
+    fds: VecDeque<OwnedFd>,
+}
+
+// The events of `wl_display` are handled by the connection itself.
+enum InternalEvent {
+    DisplayError {
+        id: u32,
+        code: u32,
+        message: String,
+    },
+    DisplayDeleteId(u32),
+    Application {
+        sender: u32,
+        opcode: u16,
+        args: Vec<Value>,
+    },
 }
 
 impl Connection {
     pub fn get_display<I: proxy::Interface, const V: u32>(&self) -> proxy::Proxy<I, V> {
-        proxy::Proxy::new(1)
+        self.state
+            .objects
+            .borrow_mut()
+            .insert_display(I::INTERFACE, V, I::EVENTS);
+        proxy::Proxy::new(self.state.clone(), 1)
     }
 
-    pub fn get_next_event<E: Event>(&self) -> Result<Option<E>, std::io::Error> {
-        todo!()
+    // Requests are buffered until the buffer fills up or this is called.
+    pub fn flush(&self) -> Result<(), std::io::Error> {
+        let mut outgoing = self.state.outgoing.borrow_mut();
+        if let Some(error) = outgoing.error.take() {
+            return Err(error);
+        }
+        self.state.flush(&mut outgoing)
     }
 
-    pub fn wait_next_event<E: Event>(&self) -> Result<E, std::io::Error> {
-        todo!()
+    // Passes the events that were already read to the handlers of their objects, and
+    // returns how many there were. Events of objects without a handler are dropped.
+    pub fn dispatch_pending<D: 'static>(&self, state: &mut D) -> Result<usize, DispatchError> {
+        let mut dispatched = 0;
+        while let Some(event) = self.state.next_event()? {
+            match event {
+                InternalEvent::DisplayError { id, code, message } => {
+                    let interface = match self.state.objects.borrow().get(id) {
+                        Some(Object::Live { interface, .. }) => interface.to_string(),
+                        _ => String::new(),
+                    };
+                    return Err(DispatchError::Protocol {
+                        interface,
+                        id,
+                        code,
+                        message,
+                    });
+                }
+                InternalEvent::DisplayDeleteId(id) => self.state.objects.borrow_mut().remove(id),
+                InternalEvent::Application {
+                    sender,
+                    opcode,
+                    args,
+                } => self.state.dispatch(state, sender, opcode, args),
+            }
+            dispatched += 1;
+        }
+        Ok(dispatched)
     }
 
-    pub fn allocate_id(&self) -> u32 {
-        0
+    // Flushes, then waits for events if none were read yet and dispatches them.
+    pub fn blocking_dispatch<D: 'static>(&self, state: &mut D) -> Result<usize, DispatchError> {
+        self.flush()?;
+        loop {
+            let dispatched = self.dispatch_pending(state)?;
+            if dispatched > 0 {
+                return Ok(dispatched);
+            }
+            self.state.read()?;
+        }
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn default() -> Result<Self, ConnectError> {
         if let Ok(wayland_socket) = std::env::var("WAYLAND_SOCKET") {
             return Ok(Self::from_fd(unsafe {
@@ -55,10 +133,7 @@ impl Connection {
     }
 
     pub fn from_fd(fd: OwnedFd) -> Self {
-        eprintln!("Using fd {:?}", fd);
-        Self {
-            stream: UnixStream::from(fd),
-        }
+        Self::from_stream(UnixStream::from(fd))
     }
 
     pub fn from_path(path: String) -> Result<Self, ConnectError> {
@@ -73,12 +148,223 @@ impl Connection {
         let stream =
             UnixStream::connect(&path).map_err(|_| ConnectError::InvalidDisplayPath(path))?;
 
-        Ok(Self { stream })
+        Ok(Self::from_stream(stream))
+    }
+
+    fn from_stream(stream: UnixStream) -> Self {
+        Self {
+            state: Rc::new(State {
+                stream,
+                objects: RefCell::new(ObjectMap::default()),
+                outgoing: RefCell::new(Outgoing::default()),
+                incoming: RefCell::new(Incoming::default()),
+                handlers: RefCell::new(HashMap::new()),
+            }),
+        }
     }
 }
 
-pub trait Event: Sized {
-    fn from_bytes(bytes: &[u8]) -> Self;
+impl State {
+    // Panics when the request is newer than the object, as libwayland aborts there too.
+    pub(crate) fn send_request(&self, sender: u32, opcode: u16, since: u32, args: &[Argument]) {
+        match self.objects.borrow().get(sender) {
+            Some(Object::Live {
+                interface, version, ..
+            }) if *version < since => panic!(
+                "request {opcode} of `{interface}` needs version {since}, but object {sender} is version {version}"
+            ),
+            Some(Object::Live { .. }) => {}
+            _ => panic!("object {sender} was destroyed"),
+        }
+
+        let mut bytes = Vec::<u8>::new();
+        let mut fds = Vec::<OwnedFd>::new();
+        let mut outgoing = self.outgoing.borrow_mut();
+        if let Err(e) = wire::encode(sender, opcode, args, &mut bytes, &mut fds) {
+            outgoing.error.get_or_insert(e);
+            return;
+        }
+
+        if (outgoing.bytes.len() + bytes.len() > MAX_BUFFER_SIZE
+            || outgoing.fds.len() + fds.len() > MAX_FDS)
+            && let Err(e) = self.flush(&mut outgoing)
+        {
+            outgoing.error.get_or_insert(e);
+        }
+        outgoing.bytes.append(&mut bytes);
+        outgoing.fds.append(&mut fds);
+    }
+
+    pub(crate) fn create(
+        &self,
+        interface: &'static str,
+        version: u32,
+        events: &'static [EventSignature],
+    ) -> u32 {
+        self.objects
+            .borrow_mut()
+            .allocate(interface, version, events)
+    }
+
+    pub(crate) fn destroy(&self, id: u32) {
+        self.objects.borrow_mut().zombify(id);
+        self.handlers.borrow_mut().remove(&id);
+    }
+
+    pub(crate) fn set_handler<D: 'static>(&self, id: u32, handler: Handler<D>) {
+        self.handlers.borrow_mut().insert(id, Box::new(handler));
+    }
+
+    // The handler is taken out while it runs, so that it may create objects and register
+    // handlers itself.
+    fn dispatch<D: 'static>(&self, state: &mut D, sender: u32, opcode: u16, args: Vec<Value>) {
+        let Some(handler) = self.handlers.borrow_mut().remove(&sender) else {
+            return;
+        };
+        let mut handler = handler.downcast::<Handler<D>>().unwrap_or_else(|_| {
+            panic!("the handler of object {sender} was registered with another state type")
+        });
+        handler(state, opcode, args);
+
+        if let Some(Object::Live { .. }) = self.objects.borrow().get(sender) {
+            self.handlers.borrow_mut().entry(sender).or_insert(handler);
+        }
+    }
+
+    // Decodes the next complete message. Objects created by an event are added right
+    // away, destroyed ones are kept as zombies until the server deletes their id, and
+    // events sent to zombies are skipped.
+    fn next_event(&self) -> Result<Option<InternalEvent>, DispatchError> {
+        let mut incoming = self.incoming.borrow_mut();
+        loop {
+            let Some((header, _)) = incoming.bytes.split_first_chunk::<HEADER_SIZE>() else {
+                return Ok(None);
+            };
+            let sender = u32::from_ne_bytes(header[..4].try_into().unwrap());
+            let word = u32::from_ne_bytes(header[4..].try_into().unwrap());
+            let (size, opcode) = ((word >> 16) as usize, word as u16);
+            if size < HEADER_SIZE {
+                return Err(DispatchError::InvalidMessage);
+            }
+            if incoming.bytes.len() < size {
+                return Ok(None);
+            }
+
+            let mut objects = self.objects.borrow_mut();
+            let (version, events) = match objects.get(sender) {
+                Some(Object::Live {
+                    version, events, ..
+                }) => (Some(*version), *events),
+                Some(Object::Zombie { events }) => (None, *events),
+                None => return Err(DispatchError::InvalidMessage),
+            };
+            let event = events
+                .get(opcode as usize)
+                .ok_or(DispatchError::InvalidMessage)?;
+            let Incoming { bytes, fds } = &mut *incoming;
+            let args = wire::decode(event.signature, &bytes[HEADER_SIZE..size], fds)
+                .ok_or(DispatchError::InvalidMessage)?;
+            bytes.drain(..size);
+
+            // Objects created by events to zombies are zombies themselves, but their ids
+            // are taken all the same.
+            if let Some(describe) = event.new_id {
+                let (interface, child_events) = describe();
+                for arg in &args {
+                    if let Value::NewId(id) = arg {
+                        objects
+                            .insert_at(*id, interface, version.unwrap_or(1), child_events)
+                            .map_err(|_| DispatchError::InvalidMessage)?;
+                        if version.is_none() {
+                            objects.zombify(*id);
+                        }
+                    }
+                }
+            }
+            if version.is_none() {
+                continue;
+            }
+            if event.destructor {
+                objects.zombify(sender);
+            }
+
+            return Ok(Some(match (sender, opcode, args.as_slice()) {
+                (
+                    1,
+                    0,
+                    [
+                        Value::Object(Some(id)),
+                        Value::Uint(code),
+                        Value::String(Some(message)),
+                    ],
+                ) => InternalEvent::DisplayError {
+                    id: *id,
+                    code: *code,
+                    message: message.clone(),
+                },
+                (1, 1, [Value::Uint(id)]) => InternalEvent::DisplayDeleteId(*id),
+                _ => InternalEvent::Application {
+                    sender,
+                    opcode,
+                    args,
+                },
+            }));
+        }
+    }
+
+    // Blocks until the server sends something.
+    fn read(&self) -> Result<(), std::io::Error> {
+        let mut buffer = [0; MAX_BUFFER_SIZE];
+        let mut ancillary_buffer = [0; 256];
+        let mut ancillary = SocketAncillary::new(&mut ancillary_buffer);
+        let read = self
+            .stream
+            .recv_vectored_with_ancillary(&mut [IoSliceMut::new(&mut buffer)], &mut ancillary)?;
+        if read == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        let mut incoming = self.incoming.borrow_mut();
+        incoming.bytes.extend_from_slice(&buffer[..read]);
+        for message in ancillary.messages() {
+            if let Ok(AncillaryData::ScmRights(rights)) = message {
+                incoming
+                    .fds
+                    .extend(rights.map(|fd| unsafe { OwnedFd::from_raw_fd(fd) }));
+            }
+        }
+        Ok(())
+    }
+
+    // What was sent is removed from the buffer right away, so that a flush that fails
+    // halfway leaves only the rest to the next one.
+    fn flush(&self, outgoing: &mut Outgoing) -> Result<(), std::io::Error> {
+        while !outgoing.bytes.is_empty() {
+            let fds = outgoing
+                .fds
+                .iter()
+                .take(MAX_FDS)
+                .map(|fd| fd.as_raw_fd())
+                .collect::<Vec<RawFd>>();
+            let mut buffer = [0; 256];
+            let mut ancillary = SocketAncillary::new(&mut buffer);
+            ancillary.add_fds(&fds);
+
+            let slice = [IoSlice::new(&outgoing.bytes)];
+            match self
+                .stream
+                .send_vectored_with_ancillary(&slice, &mut ancillary)
+            {
+                Ok(written) => {
+                    outgoing.bytes.drain(..written);
+                    outgoing.fds.drain(..fds.len());
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -86,3 +372,22 @@ pub enum ConnectError {
     InvalidDisplayPath(PathBuf),
     NoXdgRuntimeDir,
 }
+
+#[derive(Debug)]
+pub enum DispatchError {
+    Io(io::Error),
+    // A `wl_display.error`, the server closes the connection after sending it.
+    Protocol {
+        interface: String,
+        id: u32,
+        code: u32,
+        message: String,
+    },
+    InvalidMessage,
+}
+
+impl From<io::Error> for DispatchError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
@@ -17,7 +17,7 @@ impl Eq for Fixed {}
 
 impl PartialOrd for Fixed {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.0.partial_cmp(&other.0)
+        Some(self.cmp(other))
     }
 }
 
@@ -284,6 +284,14 @@ impl From<Fixed> for usize {
 }
 
 impl Fixed {
+    pub const fn from_raw(raw: i32) -> Fixed {
+        Fixed(raw)
+    }
+
+    pub const fn to_raw(self) -> i32 {
+        self.0
+    }
+
     pub const fn abs(self) -> Fixed {
         Fixed(self.0.abs())
     }
@@ -1,4 +1,6 @@
-#![feature(f16, f128)]
+#![feature(f16, f128, unix_socket_ancillary_data)]
 pub mod connection;
 pub mod fixed;
+mod object_map;
 pub mod proxy;
+pub mod wire;
@@ -0,0 +1,202 @@
+use crate::proxy::EventSignature;
+
+// Ids from here on are allocated by the server, for objects created by events.
+const SERVER_ID_START: u32 = 0xFF000000;
+
+pub enum Object {
+    Live {
+        interface: &'static str,
+        version: u32,
+        events: &'static [EventSignature],
+    },
+    // Destroyed, but the server may still send events to it until it acknowledges the
+    // destruction with `wl_display.delete_id`. The signatures are kept to skip them.
+    Zombie {
+        events: &'static [EventSignature],
+    },
+}
+
+// Client ids start at 1, which is always `wl_display`.
+#[derive(Default)]
+pub struct ObjectMap {
+    objects: Vec<Option<Object>>,
+    server_objects: Vec<Option<Object>>,
+}
+
+impl ObjectMap {
+    pub fn insert_display(
+        &mut self,
+        interface: &'static str,
+        version: u32,
+        events: &'static [EventSignature],
+    ) {
+        if self.objects.is_empty() {
+            self.objects.push(None);
+        }
+        self.objects[0] = Some(Object::Live {
+            interface,
+            version,
+            events,
+        });
+    }
+
+    // For the new ids of events. The server allocates them like the client does, so a
+    // valid id is free and at most one past the ids it used so far.
+    pub fn insert_at(
+        &mut self,
+        id: u32,
+        interface: &'static str,
+        version: u32,
+        events: &'static [EventSignature],
+    ) -> Result<(), IdError> {
+        let index = id.checked_sub(SERVER_ID_START).ok_or(IdError::OutOfRange)? as usize;
+        match self.server_objects.get(index) {
+            Some(Some(_)) => return Err(IdError::InUse),
+            Some(None) => {}
+            None if index == self.server_objects.len() => self.server_objects.push(None),
+            None => return Err(IdError::OutOfSequence),
+        }
+        self.server_objects[index] = Some(Object::Live {
+            interface,
+            version,
+            events,
+        });
+        Ok(())
+    }
+
+    // Takes the lowest free id, like libwayland does.
+    pub fn allocate(
+        &mut self,
+        interface: &'static str,
+        version: u32,
+        events: &'static [EventSignature],
+    ) -> u32 {
+        let object = Some(Object::Live {
+            interface,
+            version,
+            events,
+        });
+        let index = match self.objects.iter().position(Option::is_none) {
+            Some(index) => {
+                self.objects[index] = object;
+                index
+            }
+            None => {
+                self.objects.push(object);
+                self.objects.len() - 1
+            }
+        };
+        index as u32 + 1
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Object> {
+        match id.checked_sub(SERVER_ID_START) {
+            Some(index) => self.server_objects.get(index as usize)?.as_ref(),
+            None => self.objects.get((id as usize).checked_sub(1)?)?.as_ref(),
+        }
+    }
+
+    pub fn zombify(&mut self, id: u32) {
+        if let Some(object) = self.slot(id)
+            && let Some(Object::Live { events, .. }) = *object
+        {
+            *object = Some(Object::Zombie { events });
+        }
+    }
+
+    // Frees the id of a zombie. Live objects are kept, the server has no business
+    // deleting them.
+    pub fn remove(&mut self, id: u32) {
+        if let Some(object) = self.slot(id)
+            && let Some(Object::Zombie { .. }) = object
+        {
+            *object = None;
+        }
+    }
+
+    fn slot(&mut self, id: u32) -> Option<&mut Option<Object>> {
+        match id.checked_sub(SERVER_ID_START) {
+            Some(index) => self.server_objects.get_mut(index as usize),
+            None => self.objects.get_mut((id as usize).checked_sub(1)?),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum IdError {
+    // Not in the range of server-created ids.
+    OutOfRange,
+    InUse,
+    // Past the next free id.
+    OutOfSequence,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IdError, Object, ObjectMap, SERVER_ID_START};
+
+    #[test]
+    fn zombify() {
+        let mut objects = ObjectMap::default();
+        objects.insert_display("wl_display", 1, &[]);
+        objects.allocate("wl_registry", 1, &[]);
+        objects.allocate("wl_callback", 1, &[]);
+        objects.zombify(3);
+
+        assert!(matches!(objects.get(1), Some(Object::Live { .. })));
+        assert!(matches!(objects.get(2), Some(Object::Live { .. })));
+        assert!(matches!(objects.get(3), Some(Object::Zombie { .. })));
+
+        objects.remove(1);
+        objects.remove(3);
+        assert!(matches!(objects.get(1), Some(Object::Live { .. })));
+        assert!(objects.get(3).is_none());
+    }
+
+    #[test]
+    fn allocate() {
+        let mut objects = ObjectMap::default();
+        objects.insert_display("wl_display", 1, &[]);
+        objects.allocate("wl_registry", 1, &[]);
+        objects.allocate("wl_callback", 1, &[]);
+        objects.zombify(2);
+        objects.remove(2);
+        objects
+            .insert_at(SERVER_ID_START, "wl_data_offer", 3, &[])
+            .unwrap();
+
+        assert_eq!(2, objects.allocate("wl_registry", 1, &[]));
+        assert_eq!(4, objects.allocate("wl_callback", 1, &[]));
+        assert!(objects.get(SERVER_ID_START).is_some());
+    }
+
+    #[test]
+    fn insert_at() {
+        let mut objects = ObjectMap::default();
+        objects.insert_display("wl_display", 1, &[]);
+        objects
+            .insert_at(SERVER_ID_START, "wl_data_offer", 3, &[])
+            .unwrap();
+
+        assert!(matches!(
+            objects.insert_at(0, "wl_data_offer", 3, &[]),
+            Err(IdError::OutOfRange)
+        ));
+        assert!(matches!(
+            objects.insert_at(2, "wl_data_offer", 3, &[]),
+            Err(IdError::OutOfRange)
+        ));
+        assert!(matches!(
+            objects.insert_at(SERVER_ID_START, "wl_data_offer", 3, &[]),
+            Err(IdError::InUse)
+        ));
+        assert!(matches!(
+            objects.insert_at(u32::MAX, "wl_data_offer", 3, &[]),
+            Err(IdError::OutOfSequence)
+        ));
+        objects
+            .insert_at(SERVER_ID_START + 1, "wl_data_offer", 3, &[])
+            .unwrap();
+        assert!(objects.get(2).is_none());
+    }
+}
@@ -1,18 +1,125 @@
+use std::rc::Rc;
+
+use crate::connection::State;
+use crate::wire::{Argument, Value};
+
 pub trait Interface {
     const INTERFACE: &'static str;
     const MAX_VERSION: u32;
+    // Indexed by opcode.
+    const EVENTS: &'static [EventSignature];
+}
+
+// What the connection needs to know to decode an event.
+pub struct EventSignature {
+    pub signature: &'static str,
+    pub destructor: bool,
+    // For events that create an object, its interface. A function, as interfaces may
+    // refer to each other.
+    pub new_id: Option<Describe>,
+}
+
+pub type Describe = fn() -> (&'static str, &'static [EventSignature]);
+
+pub fn describe<I: Interface>() -> (&'static str, &'static [EventSignature]) {
+    (I::INTERFACE, I::EVENTS)
 }
 
+// Implemented by generated code for every interface, calls the method of a handler `H`
+// matching the event. Objects created by the event are built from `parent`.
+pub trait Dispatcher<D, H>: Interface + Sized {
+    fn dispatch<const V: u32>(
+        handler: &mut H,
+        state: &mut D,
+        parent: &Proxy<Self, V>,
+        opcode: u16,
+        args: Vec<Value>,
+    );
+}
+
+// Not `Clone`, so that a destructor request, which takes the proxy by value, leaves
+// nothing behind to send further requests with.
 pub struct Proxy<I: Interface, const V: u32> {
     id: u32,
+    state: Rc<State>,
     _interface: std::marker::PhantomData<I>,
 }
 
 impl<I: Interface, const V: u32> Proxy<I, V> {
-    pub(crate) fn new(id: u32) -> Self {
+    pub(crate) fn new(state: Rc<State>, id: u32) -> Self {
         Self {
-            id: id,
+            id,
+            state,
             _interface: std::marker::PhantomData,
         }
     }
+
+    pub fn get_id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn get_version(&self) -> u32 {
+        V
+    }
+
+    // Used by generated code.
+    #[doc(hidden)]
+    pub fn send_request(&self, opcode: u16, since: u32, args: &[Argument]) {
+        self.state.send_request(self.id, opcode, since, args);
+    }
+
+    // Events sent to this object are passed to `handler` by `Connection::dispatch_pending`
+    // with the same state type `D`. Replaces the previous handler.
+    pub fn set_handler<D: 'static, H: 'static>(&self, mut handler: H)
+    where
+        I: Dispatcher<D, H>,
+    {
+        // Weak, as the connection owns the handler.
+        let (connection, id) = (Rc::downgrade(&self.state), self.id);
+        self.state.set_handler::<D>(
+            self.id,
+            Box::new(move |state, opcode, args| {
+                let connection = connection
+                    .upgrade()
+                    .expect("events are dispatched by the connection");
+                let parent = Proxy::<I, V>::new(connection, id);
+                I::dispatch(&mut handler, state, &parent, opcode, args)
+            }),
+        );
+    }
+
+    // Used by generated code for requests with a `new_id` argument. The new object is
+    // sent along with the request, so it counts as live right away.
+    #[doc(hidden)]
+    pub fn create_object<C: Interface, const W: u32>(&self) -> Proxy<C, W> {
+        let id = self.state.create(C::INTERFACE, W, C::EVENTS);
+        Proxy::new(self.state.clone(), id)
+    }
+
+    // Used by generated code for the objects created by events, which the connection
+    // added when it decoded them, with the version of their parent.
+    #[doc(hidden)]
+    pub fn child<C: Interface>(&self, id: u32) -> Proxy<C, V> {
+        Proxy::new(self.state.clone(), id)
+    }
+
+    // Used by generated code for destructor requests. The id stays taken until the
+    // server confirms the destruction.
+    #[doc(hidden)]
+    pub fn send_destructor(self, opcode: u16, since: u32, args: &[Argument]) {
+        self.state.send_request(self.id, opcode, since, args);
+        self.state.destroy(self.id);
+    }
+}
+
+// An object of interface `I` of any version, as taken by requests with an `object`
+// argument.
+pub trait Object<I: Interface> {
+    fn get_id(&self) -> u32;
+}
+
+impl<I: Interface, const V: u32> Object<I> for Proxy<I, V> {
+    fn get_id(&self) -> u32 {
+        self.id
+    }
 }
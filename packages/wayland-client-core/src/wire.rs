@@ -0,0 +1,229 @@
+use std::collections::VecDeque;
+use std::io;
+use std::os::fd::{BorrowedFd, OwnedFd};
+
+use crate::fixed::Fixed;
+
+pub const HEADER_SIZE: usize = 8;
+
+// An argument of an outgoing message, in the order of its signature.
+pub enum Argument<'a> {
+    Int(i32),
+    Uint(u32),
+    Fixed(Fixed),
+    String(Option<&'a str>),
+    Object(Option<u32>),
+    NewId(u32),
+    Array(&'a [u8]),
+    Fd(BorrowedFd<'a>),
+}
+
+// An argument of an incoming message.
+pub enum Value {
+    Int(i32),
+    Uint(u32),
+    Fixed(Fixed),
+    String(Option<String>),
+    Object(Option<u32>),
+    NewId(u32),
+    Array(Vec<u8>),
+    Fd(OwnedFd),
+}
+
+// Appends a message to `bytes`. File descriptors are not part of the message body, they
+// are duplicated into `fds` and sent alongside it. Fails with `InvalidInput`, appending
+// nothing, for a string with an interior NUL or a message too large for its 16-bit size.
+pub fn encode(
+    sender: u32,
+    opcode: u16,
+    args: &[Argument],
+    bytes: &mut Vec<u8>,
+    fds: &mut Vec<OwnedFd>,
+) -> io::Result<()> {
+    let (start, fds_start) = (bytes.len(), fds.len());
+    let result = write_message(sender, opcode, args, bytes, fds);
+    if result.is_err() {
+        bytes.truncate(start);
+        fds.truncate(fds_start);
+    }
+    result
+}
+
+fn write_message(
+    sender: u32,
+    opcode: u16,
+    args: &[Argument],
+    bytes: &mut Vec<u8>,
+    fds: &mut Vec<OwnedFd>,
+) -> io::Result<()> {
+    let start = bytes.len();
+    bytes.extend_from_slice(&sender.to_ne_bytes());
+    bytes.extend_from_slice(&[0; 4]);
+
+    for arg in args {
+        match arg {
+            Argument::Int(value) => bytes.extend_from_slice(&value.to_ne_bytes()),
+            Argument::Uint(value) | Argument::NewId(value) => {
+                bytes.extend_from_slice(&value.to_ne_bytes())
+            }
+            Argument::Fixed(value) => bytes.extend_from_slice(&value.to_raw().to_ne_bytes()),
+            Argument::String(None) => bytes.extend_from_slice(&0_u32.to_ne_bytes()),
+            Argument::String(Some(value)) => {
+                if value.contains('\0') {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "string argument with an interior NUL",
+                    ));
+                }
+                let mut string = value.as_bytes().to_vec();
+                string.push(0);
+                write_array(bytes, &string);
+            }
+            Argument::Object(id) => bytes.extend_from_slice(&id.unwrap_or(0).to_ne_bytes()),
+            Argument::Array(value) => write_array(bytes, value),
+            Argument::Fd(fd) => fds.push(fd.try_clone_to_owned()?),
+        }
+    }
+
+    let size = u16::try_from(bytes.len() - start).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("message of {} bytes", bytes.len() - start),
+        )
+    })?;
+    let header = ((size as u32) << 16) | opcode as u32;
+    bytes[start + 4..start + 8].copy_from_slice(&header.to_ne_bytes());
+    Ok(())
+}
+
+// Reads the body of a message with the given libwayland signature, taking its file
+// descriptors from `fds`. Returns `None` when the body does not match the signature.
+pub fn decode(signature: &str, body: &[u8], fds: &mut VecDeque<OwnedFd>) -> Option<Vec<Value>> {
+    let mut values = Vec::new();
+    let mut words = body;
+    let mut nullable = false;
+    for c in signature.chars() {
+        let value = match c {
+            '0'..='9' => continue,
+            '?' => {
+                nullable = true;
+                continue;
+            }
+            'i' => Value::Int(read_word(&mut words)? as i32),
+            'u' => Value::Uint(read_word(&mut words)?),
+            'f' => Value::Fixed(Fixed::from_raw(read_word(&mut words)? as i32)),
+            's' => match read_array(&mut words)? {
+                [] => Value::String(None),
+                [string @ .., 0] => Value::String(Some(String::from_utf8(string.to_vec()).ok()?)),
+                _ => return None,
+            },
+            'o' => Value::Object(Some(read_word(&mut words)?).filter(|id| *id != 0)),
+            'n' => Value::NewId(read_word(&mut words)?),
+            'a' => Value::Array(read_array(&mut words)?.to_vec()),
+            'h' => Value::Fd(fds.pop_front()?),
+            _ => return None,
+        };
+        if !nullable && matches!(value, Value::String(None) | Value::Object(None)) {
+            return None;
+        }
+        nullable = false;
+        values.push(value);
+    }
+    words.is_empty().then_some(values)
+}
+
+fn read_word(words: &mut &[u8]) -> Option<u32> {
+    let (word, rest) = words.split_first_chunk::<4>()?;
+    *words = rest;
+    Some(u32::from_ne_bytes(*word))
+}
+
+fn read_array<'a>(words: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = read_word(words)? as usize;
+    let padded = len.checked_next_multiple_of(4)?;
+    if words.len() < padded {
+        return None;
+    }
+    let array = &words[..len];
+    *words = &words[padded..];
+    Some(array)
+}
+
+fn write_array(bytes: &mut Vec<u8>, array: &[u8]) {
+    bytes.extend_from_slice(&(array.len() as u32).to_ne_bytes());
+    bytes.extend_from_slice(array);
+    let padding = (4 - array.len() % 4) % 4;
+    bytes.extend_from_slice(&[0; 3][..padding]);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::io::ErrorKind;
+
+    use super::{Argument, Value, decode, encode};
+
+    #[test]
+    fn encode_message() {
+        let mut bytes = Vec::<u8>::new();
+        let mut fds = Vec::new();
+        let args = [
+            Argument::Uint(7),
+            Argument::String(Some("wl_compositor")),
+            Argument::Object(None),
+        ];
+        encode(2, 1, &args, &mut bytes, &mut fds).unwrap();
+
+        let words = bytes
+            .chunks(4)
+            .map(|w| u32::from_ne_bytes(w.try_into().unwrap()))
+            .collect::<Vec<u32>>();
+        assert_eq!(2, words[0]);
+        assert_eq!((36 << 16) | 1, words[1]);
+        assert_eq!(7, words[2]);
+        assert_eq!(14, words[3]);
+        assert_eq!(b"wl_compositor\0\0\0", &bytes[16..32]);
+        assert_eq!(0, words[8]);
+    }
+
+    #[test]
+    fn encode_invalid() {
+        let mut bytes = vec![1, 2, 3, 4];
+        let mut fds = Vec::new();
+        let array = vec![0; 0xFFFF];
+        let args = [Argument::Uint(1), Argument::Array(&array)];
+        let error = encode(2, 0, &args, &mut bytes, &mut fds).unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, error.kind());
+        assert_eq!(vec![1, 2, 3, 4], bytes);
+
+        let args = [Argument::String(Some("wl_\0compositor"))];
+        let error = encode(2, 0, &args, &mut bytes, &mut fds).unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, error.kind());
+        assert_eq!(vec![1, 2, 3, 4], bytes);
+
+        // The largest message that fits.
+        let array = vec![0; 0xFFFF - 12 - 3];
+        encode(2, 0, &[Argument::Array(&array)], &mut bytes, &mut fds).unwrap();
+        assert_eq!(4 + 0xFFFF - 3, bytes.len());
+    }
+
+    #[test]
+    fn decode_message() {
+        let mut bytes = Vec::<u8>::new();
+        let args = [
+            Argument::Object(Some(3)),
+            Argument::String(Some("no such object")),
+            Argument::Object(None),
+        ];
+        encode(1, 0, &args, &mut bytes, &mut Vec::new()).unwrap();
+        let body = &bytes[8..];
+
+        let values = decode("os?o", body, &mut VecDeque::new()).unwrap();
+        assert!(matches!(values[0], Value::Object(Some(3))));
+        assert!(matches!(&values[1], Value::String(Some(s)) if s == "no such object"));
+        assert!(matches!(values[2], Value::Object(None)));
+
+        assert!(decode("oso", body, &mut VecDeque::new()).is_none());
+        assert!(decode("os", body, &mut VecDeque::new()).is_none());
+    }
+}
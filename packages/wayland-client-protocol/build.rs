@@ -0,0 +1,23 @@
+use std::env;
+
+use wayland_scanner::Family;
+
+fn main() {
+    let mut families = vec![Family::Core, Family::Stable];
+    for (feature, family) in [
+        ("CARGO_FEATURE_STAGING", Family::Staging),
+        ("CARGO_FEATURE_UNSTABLE", Family::Unstable),
+        ("CARGO_FEATURE_EXPERIMENTAL", Family::Experimental),
+        ("CARGO_FEATURE_WLR", Family::Wlr),
+        ("CARGO_FEATURE_KDE", Family::Kde),
+    ] {
+        if env::var_os(feature).is_some() {
+            families.push(family);
+        }
+    }
+
+    let protocols =
+        wayland_scanner::generate("protocols.rs", &families, &[]).unwrap_or_else(|e| panic!("{e}"));
+    wayland_scanner::export_externals(&protocols, "wayland_client_protocol::wl")
+        .unwrap_or_else(|e| panic!("{e}"));
+}
@@ -1 +1,213 @@
-#[proc_macro]
+// The core protocol and stable wayland-protocols are always generated, the other
+// families are behind the `staging`, `unstable`, `experimental`, `wlr` and `kde`
+// features.
+//
+// Crates with their own protocols can refer to these types by generating their code
+// from a build script:
+//
+//     let externals = wayland_scanner::import_externals("wayland-client-protocol")?;
+//     wayland_scanner::generate_from("protocols.rs", &["protocol/foo.xml"], &externals)?;
+include!(concat!(env!("OUT_DIR"), "/protocols.rs"));
+
+// The version of an object created with an unspecified `new_id` is checked against
+// the interface when the request is compiled:
+//
+/// ```compile_fail
+/// use wayland_client_core::connection::Connection;
+/// use wayland_client_protocol::wl::{self, prelude::*};
+///
+/// let (stream, _) = std::os::unix::net::UnixStream::pair().unwrap();
+/// let conn = Connection::from_fd(stream.into());
+/// let registry = conn.get_display::<wl::Display, 1>().get_registry();
+/// registry.bind::<wl::Compositor, 99>(1);
+/// ```
+#[cfg(doctest)]
+pub struct BindVersionAboveInterface;
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixStream;
+
+    use wayland_client_core::connection::{Connection, DispatchError};
+    use wayland_client_core::proxy::Proxy;
+
+    use crate::wl::{self, prelude::*};
+
+    // An event as the server writes it, the body given in words.
+    fn event(sender: u32, opcode: u32, body: &[u32]) -> Vec<u8> {
+        let size = 8 + 4 * body.len() as u32;
+        [sender, (size << 16) | opcode]
+            .iter()
+            .chain(body)
+            .flat_map(|word| word.to_ne_bytes())
+            .collect()
+    }
+
+    fn read_words(peer: &mut UnixStream, count: usize) -> Vec<u32> {
+        let mut bytes = vec![0; 4 * count];
+        peer.read_exact(&mut bytes).unwrap();
+        bytes
+            .chunks(4)
+            .map(|word| u32::from_ne_bytes(word.try_into().unwrap()))
+            .collect()
+    }
+
+    #[derive(Default)]
+    struct App {
+        done: Vec<(&'static str, u32)>,
+        enters: usize,
+    }
+
+    struct Callback(&'static str);
+
+    impl wl::callback::Dispatch<App> for Callback {
+        fn done(&mut self, state: &mut App, event: wl::callback::DoneEvent) {
+            state.done.push((self.0, event.callback_data));
+        }
+    }
+
+    struct Surface;
+
+    impl wl::surface::Dispatch<App> for Surface {
+        fn enter(&mut self, state: &mut App, _: wl::surface::EnterEvent) {
+            state.enters += 1;
+        }
+    }
+
+    #[test]
+    fn typed_new_ids() {
+        let (stream, mut peer) = UnixStream::pair().unwrap();
+        let conn = Connection::from_fd(stream.into());
+        let display = conn.get_display::<wl::Display, 1>();
+        let registry = display.get_registry();
+        let compositor: Proxy<wl::Compositor, 5> = registry.bind::<wl::Compositor, 5>(7);
+        let surface: Proxy<wl::Surface, 5> = compositor.create_surface();
+        assert_eq!((3, 5), (compositor.get_id(), compositor.get_version()));
+        assert_eq!((4, 5), (surface.get_id(), surface.get_version()));
+        conn.flush().unwrap();
+
+        // `wl_display.get_registry`, then `wl_registry.bind` with the interface name
+        // and version of the unspecified `new_id`.
+        assert_eq!(vec![1, (12 << 16) | 1, 2], read_words(&mut peer, 3));
+        let bind = read_words(&mut peer, 10);
+        assert_eq!([2, (40 << 16), 7, 14], bind[..4]);
+        assert_eq!(
+            b"wl_compositor\0\0\0",
+            &bind[4..8]
+                .iter()
+                .flat_map(|w| w.to_ne_bytes())
+                .collect::<Vec<u8>>()[..]
+        );
+        assert_eq!([5, 3], bind[8..]);
+    }
+
+    #[test]
+    fn handlers() {
+        let (stream, mut peer) = UnixStream::pair().unwrap();
+        let conn = Connection::from_fd(stream.into());
+        let display = conn.get_display::<wl::Display, 1>();
+        let first = display.sync();
+        first.set_handler(Callback("first"));
+        let second = display.sync();
+        second.set_handler(Callback("replaced"));
+        second.set_handler(Callback("second"));
+
+        let mut bytes = event(3, 0, &[20]);
+        bytes.extend(event(2, 0, &[10]));
+        peer.write_all(&bytes).unwrap();
+        let mut app = App::default();
+        assert_eq!(2, conn.blocking_dispatch(&mut app).unwrap());
+        assert_eq!(vec![("second", 20), ("first", 10)], app.done);
+    }
+
+    #[test]
+    fn zombies() {
+        let (stream, mut peer) = UnixStream::pair().unwrap();
+        let conn = Connection::from_fd(stream.into());
+        let display = conn.get_display::<wl::Display, 1>();
+        let compositor = display.get_registry().bind::<wl::Compositor, 6>(1);
+        let surface = compositor.create_surface();
+        surface.set_handler(Surface);
+        let output = 3;
+
+        // Sent by the server before it saw the destructor.
+        surface.destroy();
+        let mut bytes = event(4, 0, &[output]);
+        // `wl_display.delete_id`, after which the id is free again.
+        bytes.extend(event(1, 1, &[4]));
+        peer.write_all(&bytes).unwrap();
+        let mut app = App::default();
+        assert_eq!(1, conn.blocking_dispatch(&mut app).unwrap());
+        assert_eq!(0, app.enters);
+
+        let surface = compositor.create_surface();
+        assert_eq!(4, surface.get_id());
+        surface.set_handler(Surface);
+        peer.write_all(&event(4, 0, &[output])).unwrap();
+        assert_eq!(1, conn.blocking_dispatch(&mut app).unwrap());
+        assert_eq!(1, app.enters);
+    }
+
+    #[derive(Default)]
+    struct Offers {
+        mime_types: Vec<String>,
+    }
+
+    struct DataDevice;
+
+    impl wl::data_device::Dispatch<Offers> for DataDevice {
+        fn data_offer<const V: u32>(
+            &mut self,
+            _: &mut Offers,
+            event: wl::data_device::DataOfferEvent<V>,
+        ) {
+            assert_eq!(0xFF000000, event.id.get_id());
+            assert_eq!(3, event.id.get_version());
+            event.id.set_handler(DataOffer);
+        }
+    }
+
+    struct DataOffer;
+
+    impl wl::data_offer::Dispatch<Offers> for DataOffer {
+        fn offer(&mut self, state: &mut Offers, event: wl::data_offer::OfferEvent) {
+            state.mime_types.push(event.mime_type);
+        }
+    }
+
+    #[test]
+    fn server_objects() {
+        let (stream, mut peer) = UnixStream::pair().unwrap();
+        let conn = Connection::from_fd(stream.into());
+        let registry = conn.get_display::<wl::Display, 1>().get_registry();
+        let seat = registry.bind::<wl::Seat, 3>(1);
+        let manager = registry.bind::<wl::DataDeviceManager, 3>(2);
+        let device = manager.get_data_device(&seat);
+        device.set_handler(DataDevice);
+
+        // `wl_data_device.data_offer`, then `wl_data_offer.offer` to the new object.
+        let mut bytes = event(5, 0, &[0xFF000000]);
+        bytes.extend(event(
+            0xFF000000,
+            0,
+            &[
+                11,
+                u32::from_ne_bytes(*b"text"),
+                u32::from_ne_bytes(*b"/pla"),
+                u32::from_ne_bytes(*b"in\0\0"),
+            ],
+        ));
+        peer.write_all(&bytes).unwrap();
+        let mut offers = Offers::default();
+        assert_eq!(2, conn.blocking_dispatch(&mut offers).unwrap());
+        assert_eq!(vec!["text/plain"], offers.mime_types);
+
+        // Skipping ahead of the ids the server used so far.
+        peer.write_all(&event(5, 0, &[0xFF000005])).unwrap();
+        assert!(matches!(
+            conn.blocking_dispatch(&mut offers),
+            Err(DispatchError::InvalidMessage)
+        ));
+    }
+}
@@ -1,2 +1,2 @@
 pub use wayland_client_core;
-pub mod protocol {}
+pub use wayland_client_protocol::wl;
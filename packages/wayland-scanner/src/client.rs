@@ -0,0 +1,507 @@
+use crate::constants::{interface_constants, signature};
+use crate::namespace::Namespace;
+use crate::naming::{self, unique, value_ident, value_name, variant_names};
+use crate::{Arg, ArgType, Enum, Event, EventType, Interface, Protocol, Request, RequestType};
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::Ident;
+
+pub trait GenClientTokens {
+    fn to_tokens(self, namespace: &Namespace) -> TokenStream;
+}
+
+impl GenClientTokens for &Protocol {
+    fn to_tokens(self, namespace: &Namespace) -> TokenStream {
+        let interfaces = self
+            .interfaces
+            .iter()
+            .map(|i| i.to_tokens(namespace))
+            .collect::<Vec<TokenStream>>();
+
+        quote! {
+            #( #interfaces )*
+        }
+    }
+}
+
+impl GenClientTokens for &Interface {
+    fn to_tokens(self, namespace: &Namespace) -> TokenStream {
+        let flat = namespace
+            .get(&self.name)
+            .expect("every parsed interface is registered in the namespace");
+        let name = Ident::new(&flat.module, Span::call_site());
+        let type_name = Ident::new(&flat.type_name, Span::call_site());
+        let request_names = unique(self.requests.iter().map(|r| value_name(&r.name)).collect());
+        let request_signatures = self
+            .requests
+            .iter()
+            .zip(&request_names)
+            .map(|(r, name)| request_signature(r, name, namespace))
+            .collect::<Vec<TokenStream>>();
+        let requests = self
+            .requests
+            .iter()
+            .zip(&request_names)
+            .enumerate()
+            .map(|(opcode, (r, name))| {
+                let signature = request_signature(r, name, namespace);
+                let body = request_body(r, opcode as u16, namespace);
+                quote! {
+                    #signature {
+                        #body
+                    }
+                }
+            })
+            .collect::<Vec<TokenStream>>();
+        let event_names = unique(
+            self.events
+                .iter()
+                .map(|e| naming::type_name(&e.name) + "Event")
+                .collect(),
+        );
+        let events = self
+            .events
+            .iter()
+            .zip(&event_names)
+            .map(|(e, name)| (e, name.as_str()).to_tokens(namespace))
+            .collect::<Vec<TokenStream>>();
+        let enum_names = unique(
+            self.enums
+                .iter()
+                .map(|e| naming::type_name(&e.name))
+                .collect(),
+        );
+        let enums = self
+            .enums
+            .iter()
+            .zip(&enum_names)
+            .map(|(e, name)| (e, name.as_str()).to_tokens(namespace))
+            .collect::<Vec<TokenStream>>();
+        let event_signatures = self
+            .events
+            .iter()
+            .map(|e| event_signature(e, namespace))
+            .collect::<Vec<TokenStream>>();
+        let handlers = self
+            .events
+            .iter()
+            .zip(&event_names)
+            .map(|(e, struct_name)| {
+                let name = value_ident(&value_name(&e.name));
+                let struct_name = Ident::new(struct_name, Span::call_site());
+                let (generics, event_type) = if creates_typed(e) {
+                    (quote! { <const V: u32> }, quote! { #struct_name<V> })
+                } else {
+                    (quote! {}, quote! { #struct_name })
+                };
+                quote! {
+                    fn #name #generics(&mut self, state: &mut D, event: #event_type) {
+                        let _ = (state, event);
+                    }
+                }
+            })
+            .collect::<Vec<TokenStream>>();
+        let dispatch = dispatch_body(&self.events, &event_names);
+        let constants = interface_constants(self);
+        let interface = &self.name;
+        let max_version = self.max_version;
+
+        quote! {
+            pub use #name::#type_name;
+
+            pub mod #name {
+                pub struct #type_name {}
+
+                impl ::wayland_client_core::proxy::Interface for #type_name {
+                    const INTERFACE: &'static str = #interface;
+                    const MAX_VERSION: u32 = #max_version;
+                    const EVENTS: &'static [::wayland_client_core::proxy::EventSignature] = &[
+                        #( #event_signatures ),*
+                    ];
+                }
+
+                #constants
+
+                pub trait Requests<const V: u32> {
+                    #( #request_signatures; )*
+                }
+
+                impl<const V: u32> Requests<V> for ::wayland_client_core::proxy::Proxy<#type_name, V> {
+                    #( #requests )*
+                }
+
+                // Implemented by handlers of this interface's events, see `Proxy::set_handler`.
+                pub trait Dispatch<D> {
+                    #( #handlers )*
+                }
+
+                impl<D, H: Dispatch<D>> ::wayland_client_core::proxy::Dispatcher<D, H> for #type_name {
+                    fn dispatch<const V: u32>(
+                        handler: &mut H,
+                        state: &mut D,
+                        parent: &::wayland_client_core::proxy::Proxy<Self, V>,
+                        opcode: u16,
+                        args: Vec<::wayland_client_core::wire::Value>,
+                    ) {
+                        #dispatch
+                    }
+                }
+
+                #( #events )*
+
+                #( #enums )*
+            }
+        }
+    }
+}
+
+fn event_signature(event: &Event, namespace: &Namespace) -> TokenStream {
+    let signature = signature(event.since, &event.args);
+    let destructor = matches!(event.r#type, EventType::Destructor);
+    let new_id = event.args.iter().find_map(|arg| match &arg.r#type {
+        ArgType::NewId(interface) => Some(
+            namespace
+                .resolve(interface)
+                .expect("references are checked when the namespace is built"),
+        ),
+        _ => None,
+    });
+    let new_id = match new_id {
+        Some(path) => quote! { Some(::wayland_client_core::proxy::describe::<#path>) },
+        None => quote! { None },
+    };
+
+    quote! {
+        ::wayland_client_core::proxy::EventSignature {
+            signature: #signature,
+            destructor: #destructor,
+            new_id: #new_id,
+        }
+    }
+}
+
+// The arguments were checked against the signature when they were decoded.
+fn dispatch_body(events: &[Event], struct_names: &[String]) -> TokenStream {
+    if events.is_empty() {
+        return quote! {
+            let _ = (handler, state, parent, args);
+            unreachable!("event {opcode} of an interface without events")
+        };
+    }
+
+    let arms = events
+        .iter()
+        .zip(struct_names)
+        .enumerate()
+        .map(|(opcode, (event, struct_name))| {
+            let opcode = opcode as u16;
+            let method = value_ident(&value_name(&event.name));
+            let struct_name = Ident::new(struct_name, Span::call_site());
+            let count = event.args.len();
+            let bindings = (0..count)
+                .map(|i| Ident::new(&format!("arg{i}"), Span::call_site()))
+                .collect::<Vec<Ident>>();
+            let patterns = event.args.iter().zip(&bindings).map(|(arg, binding)| {
+                let pattern = match (&arg.r#type, arg.nullable) {
+                    (ArgType::Int | ArgType::IntEnum(_), _) => quote! { Int(#binding) },
+                    (ArgType::UInt | ArgType::Enum(_), _) => quote! { Uint(#binding) },
+                    (ArgType::Fixed, _) => quote! { Fixed(#binding) },
+                    (ArgType::String, false) => quote! { String(Some(#binding)) },
+                    (ArgType::String, true) => quote! { String(#binding) },
+                    (ArgType::Object(_) | ArgType::UnspecifiedObject, false) => {
+                        quote! { Object(Some(#binding)) }
+                    }
+                    (ArgType::Object(_) | ArgType::UnspecifiedObject, true) => {
+                        quote! { Object(#binding) }
+                    }
+                    (ArgType::NewId(_) | ArgType::UnspecifiedNewId, _) => {
+                        quote! { NewId(#binding) }
+                    }
+                    (ArgType::Array, _) => quote! { Array(#binding) },
+                    (ArgType::Fd, _) => quote! { Fd(#binding) },
+                };
+                quote! { ::wayland_client_core::wire::Value::#pattern }
+            });
+            let fields = event
+                .args
+                .iter()
+                .zip(arg_names(&event.args))
+                .zip(&bindings)
+                .map(|((arg, name), binding)| {
+                    let name = value_ident(&name);
+                    match arg.r#type {
+                        ArgType::NewId(_) if arg.nullable => {
+                            quote! { #name: Some(parent.child(#binding)) }
+                        }
+                        ArgType::NewId(_) => quote! { #name: parent.child(#binding) },
+                        ArgType::UnspecifiedNewId if arg.nullable => {
+                            quote! { #name: Some(#binding) }
+                        }
+                        _ => quote! { #name: #binding },
+                    }
+                });
+
+            quote! {
+                #opcode => {
+                    let Ok([ #( #patterns ),* ]) =
+                        <[::wayland_client_core::wire::Value; #count]>::try_from(args)
+                    else {
+                        unreachable!("arguments do not match the signature")
+                    };
+                    handler.#method(state, #struct_name { #( #fields ),* });
+                }
+            }
+        });
+
+    let unused = if events.iter().any(creates_typed) {
+        quote! {}
+    } else {
+        quote! { let _ = parent; }
+    };
+
+    quote! {
+        #unused
+        match opcode {
+            #( #arms )*
+            _ => unreachable!("unknown event {opcode}"),
+        }
+    }
+}
+
+// Destructors take the proxy by value, so that it cannot be used afterwards. Objects
+// created by the request are returned, typed ones with the version of their parent.
+fn request_signature(request: &Request, name: &str, namespace: &Namespace) -> TokenStream {
+    let name = value_ident(name);
+    let receiver = match request.r#type {
+        RequestType::Default => quote! { &self },
+        RequestType::Destructor => quote! { self },
+    };
+    let generics = if request
+        .args
+        .iter()
+        .any(|a| a.r#type == ArgType::UnspecifiedNewId)
+    {
+        quote! { <I: ::wayland_client_core::proxy::Interface, const W: u32> }
+    } else {
+        quote! {}
+    };
+    let args = request
+        .args
+        .iter()
+        .zip(arg_names(&request.args))
+        .filter(|(arg, _)| !matches!(arg.r#type, ArgType::NewId(_) | ArgType::UnspecifiedNewId))
+        .map(|(arg, name)| request_parameter(arg, &name, namespace));
+    let created = request
+        .args
+        .iter()
+        .filter_map(|arg| new_object_type(arg, namespace))
+        .collect::<Vec<TokenStream>>();
+    let output = match created.as_slice() {
+        [] => quote! {},
+        [created] => quote! { -> #created },
+        created => quote! { -> ( #( #created ),* ) },
+    };
+
+    quote! {
+        #[allow(clippy::too_many_arguments)]
+        fn #name #generics(#receiver, #( #args ),*) #output
+    }
+}
+
+fn new_object_type(arg: &Arg, namespace: &Namespace) -> Option<TokenStream> {
+    match &arg.r#type {
+        ArgType::NewId(interface) => {
+            let path = namespace
+                .resolve(interface)
+                .expect("references are checked when the namespace is built");
+            Some(quote! { ::wayland_client_core::proxy::Proxy<#path, V> })
+        }
+        ArgType::UnspecifiedNewId => Some(quote! { ::wayland_client_core::proxy::Proxy<I, W> }),
+        _ => None,
+    }
+}
+
+fn request_body(request: &Request, opcode: u16, namespace: &Namespace) -> TokenStream {
+    let since = request.since;
+    let args = request_arguments(&request.args);
+    let send = match request.r#type {
+        RequestType::Default => quote! { send_request },
+        RequestType::Destructor => quote! { send_destructor },
+    };
+    let mut created = Vec::<TokenStream>::new();
+    let mut creates = Vec::<TokenStream>::new();
+    for (arg, name) in request.args.iter().zip(arg_names(&request.args)) {
+        let ident = value_ident(&name);
+        match &arg.r#type {
+            ArgType::NewId(interface) => {
+                let path = namespace
+                    .resolve(interface)
+                    .expect("references are checked when the namespace is built");
+                creates.push(quote! { let #ident = self.create_object::<#path, V>(); });
+            }
+            ArgType::UnspecifiedNewId => creates.push(quote! {
+                const { assert!(W <= I::MAX_VERSION, "version is higher than the interface supports") };
+                let #ident = self.create_object::<I, W>();
+            }),
+            _ => continue,
+        }
+        created.push(ident);
+    }
+    let output = match created.as_slice() {
+        [] => quote! {},
+        [created] => quote! { #created },
+        created => quote! { ( #( #created ),* ) },
+    };
+
+    quote! {
+        #( #creates )*
+        self.#send(#opcode, #since, &[ #( #args ),* ]);
+        #output
+    }
+}
+
+fn request_parameter(arg: &Arg, name: &str, namespace: &Namespace) -> TokenStream {
+    let ident = value_ident(name);
+    let type_name = match &arg.r#type {
+        ArgType::Int | ArgType::IntEnum(_) => quote! { i32 },
+        ArgType::UInt | ArgType::Enum(_) => quote! { u32 },
+        ArgType::Fixed => quote! { ::wayland_client_core::fixed::Fixed },
+        ArgType::String => quote! { &str },
+        ArgType::Object(interface) => {
+            let path = namespace
+                .resolve(interface)
+                .expect("references are checked when the namespace is built");
+            quote! { &dyn ::wayland_client_core::proxy::Object<#path> }
+        }
+        ArgType::UnspecifiedObject => quote! { u32 },
+        ArgType::NewId(_) | ArgType::UnspecifiedNewId => {
+            unreachable!("new objects are returned, not passed")
+        }
+        ArgType::Array => quote! { &[u8] },
+        ArgType::Fd => quote! { ::std::os::fd::BorrowedFd<'_> },
+    };
+
+    if arg.nullable {
+        quote! { #ident: Option<#type_name> }
+    } else {
+        quote! { #ident: #type_name }
+    }
+}
+
+fn request_arguments(args: &[Arg]) -> Vec<TokenStream> {
+    let mut arguments = Vec::<TokenStream>::new();
+    for (arg, name) in args.iter().zip(arg_names(args)) {
+        let ident = value_ident(&name);
+        let argument = match (&arg.r#type, arg.nullable) {
+            (ArgType::Int | ArgType::IntEnum(_), _) => quote! { Int(#ident) },
+            (ArgType::UInt | ArgType::Enum(_), _) => quote! { Uint(#ident) },
+            (ArgType::Fixed, _) => quote! { Fixed(#ident) },
+            (ArgType::String, false) => quote! { String(Some(#ident)) },
+            (ArgType::String, true) => quote! { String(#ident) },
+            (ArgType::Object(_), false) => quote! {
+                Object(Some(::wayland_client_core::proxy::Object::get_id(#ident)))
+            },
+            (ArgType::Object(_), true) => quote! {
+                Object(#ident.map(::wayland_client_core::proxy::Object::get_id))
+            },
+            (ArgType::UnspecifiedObject, false) => quote! { Object(Some(#ident)) },
+            (ArgType::UnspecifiedObject, true) => quote! { Object(#ident) },
+            (ArgType::NewId(_), _) => quote! { NewId(#ident.get_id()) },
+            // Sent as the interface name, the version and the id.
+            (ArgType::UnspecifiedNewId, _) => {
+                arguments.push(
+                    quote! { ::wayland_client_core::wire::Argument::String(Some(I::INTERFACE)) },
+                );
+                arguments.push(quote! { ::wayland_client_core::wire::Argument::Uint(W) });
+                quote! { NewId(#ident.get_id()) }
+            }
+            (ArgType::Array, _) => quote! { Array(#ident) },
+            (ArgType::Fd, _) => quote! { Fd(#ident) },
+        };
+        arguments.push(quote! { ::wayland_client_core::wire::Argument::#argument });
+    }
+    arguments
+}
+
+// Events creating objects of a known interface are generic over the version of the
+// object they are sent to, which the new objects get.
+impl GenClientTokens for (&Event, &str) {
+    fn to_tokens(self, namespace: &Namespace) -> TokenStream {
+        let (event, name) = self;
+        let name = Ident::new(name, Span::call_site());
+        let generics = if creates_typed(event) {
+            quote! { <const V: u32> }
+        } else {
+            quote! {}
+        };
+        let fields = event
+            .args
+            .iter()
+            .zip(arg_names(&event.args))
+            .map(|(arg, name)| event_field(arg, &name, namespace));
+
+        quote! {
+            pub struct #name #generics {
+                #( #fields ),*
+            }
+        }
+    }
+}
+
+fn creates_typed(event: &Event) -> bool {
+    event
+        .args
+        .iter()
+        .any(|a| matches!(a.r#type, ArgType::NewId(_)))
+}
+
+impl GenClientTokens for (&Enum, &str) {
+    fn to_tokens(self, _namespace: &Namespace) -> TokenStream {
+        let (en, name) = self;
+        let name = Ident::new(name, Span::call_site());
+        let entry_names = variant_names(&en.name, en.entries.iter().map(|e| e.name.as_str()));
+        let entries = en.entries.iter().zip(&entry_names).map(|(entry, name)| {
+            let name = Ident::new(name, Span::call_site());
+            let value = syn::LitInt::new(entry.value.to_string().as_str(), Span::call_site());
+            quote! {#name = #value}
+        });
+
+        quote! {
+            #[repr(u32)]
+            pub enum #name {
+                #( #entries ),*
+            }
+        }
+    }
+}
+
+fn event_field(arg: &Arg, name: &str, namespace: &Namespace) -> TokenStream {
+    let ident = value_ident(name);
+    let type_name = match &arg.r#type {
+        ArgType::NewId(interface) => {
+            let path = namespace
+                .resolve(interface)
+                .expect("references are checked when the namespace is built");
+            quote! { ::wayland_client_core::proxy::Proxy<#path, V> }
+        }
+        ArgType::Int | ArgType::IntEnum(_) => quote! { i32 },
+        ArgType::UInt | ArgType::Enum(_) => quote! { u32 },
+        ArgType::Fixed => quote! { ::wayland_client_core::fixed::Fixed },
+        ArgType::String => quote! { String },
+        ArgType::Object(_) | ArgType::UnspecifiedObject | ArgType::UnspecifiedNewId => {
+            quote! { u32 }
+        }
+        ArgType::Array => quote! { Vec<u8> },
+        ArgType::Fd => quote! { ::std::os::fd::OwnedFd },
+    };
+
+    if arg.nullable {
+        quote! { pub #ident: Option<#type_name> }
+    } else {
+        quote! { pub #ident: #type_name }
+    }
+}
+
+pub(crate) fn arg_names(args: &[Arg]) -> Vec<String> {
+    unique(args.iter().map(|a| value_name(&a.name)).collect())
+}
@@ -0,0 +1,166 @@
+use std::collections::HashSet;
+
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::Ident;
+
+use crate::naming::{unique, value_name};
+use crate::{Arg, ArgType, Interface};
+
+// The wire-level constants of an interface, the same for clients and servers. Named
+// like the message methods, so that two messages never give the same constant.
+pub(crate) fn interface_constants(interface: &Interface) -> TokenStream {
+    let request_names = unique(
+        interface
+            .requests
+            .iter()
+            .map(|r| value_name(&r.name))
+            .collect(),
+    );
+    let event_names = unique(
+        interface
+            .events
+            .iter()
+            .map(|e| value_name(&e.name))
+            .collect(),
+    );
+    let requests = interface
+        .requests
+        .iter()
+        .zip(&request_names)
+        .map(|(r, name)| (name.as_str(), r.since, r.args.as_slice()))
+        .collect::<Vec<(&str, u32, &[Arg])>>();
+    let events = interface
+        .events
+        .iter()
+        .zip(&event_names)
+        .map(|(e, name)| (name.as_str(), e.since, e.args.as_slice()))
+        .collect::<Vec<(&str, u32, &[Arg])>>();
+
+    // `<NAME>_SINCE` is only qualified when a request and an event share a name.
+    let shared = event_names
+        .iter()
+        .filter(|name| request_names.contains(name))
+        .map(String::as_str)
+        .collect::<HashSet<&str>>();
+
+    let requests = messages("REQ", &requests, &shared);
+    let events = messages("EVT", &events, &shared);
+
+    quote! {
+        #( #requests )*
+        #( #events )*
+    }
+}
+
+fn messages(
+    prefix: &str,
+    messages: &[(&str, u32, &[Arg])],
+    shared: &HashSet<&str>,
+) -> Vec<TokenStream> {
+    messages
+        .iter()
+        .enumerate()
+        .map(|(opcode, (name, since, args))| {
+            let upper = name.to_ascii_uppercase();
+            let opcode_name = constant(&format!("{prefix}_{upper}_OPCODE"));
+            let since_name = if shared.contains(name) {
+                constant(&format!("{prefix}_{upper}_SINCE"))
+            } else {
+                constant(&format!("{upper}_SINCE"))
+            };
+            let signature_name = constant(&format!("{prefix}_{upper}_SIGNATURE"));
+            let opcode = opcode as u16;
+            let signature = signature(*since, args);
+
+            quote! {
+                pub const #opcode_name: u16 = #opcode;
+                pub const #since_name: u32 = #since;
+                pub const #signature_name: &str = #signature;
+            }
+        })
+        .collect()
+}
+
+fn constant(name: &str) -> Ident {
+    Ident::new(name, Span::call_site())
+}
+
+// The libwayland message signature: the version the message appeared in, when it is not
+// 1, then a character per argument, prefixed with `?` when it may be null. A `new_id`
+// without an interface is sent as the interface name, the version and the id.
+pub(crate) fn signature(since: u32, args: &[Arg]) -> String {
+    let mut signature = String::new();
+    if since > 1 {
+        signature.push_str(&since.to_string());
+    }
+    for arg in args {
+        if arg.nullable {
+            signature.push('?');
+        }
+        match arg.r#type {
+            ArgType::UnspecifiedNewId => signature.push_str("sun"),
+            _ => signature.push(arg.r#type.wire_type()),
+        }
+    }
+    signature
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{interface_constants, signature};
+    use crate::parse_protocol_str;
+
+    #[test]
+    fn signatures() {
+        let protocol = parse_protocol_str(
+            r#"<protocol name="test">
+                <interface name="test_manager" version="2">
+                    <request name="bind">
+                        <arg name="name" type="uint"/>
+                        <arg name="id" type="new_id"/>
+                    </request>
+                    <request name="set_title" since="2">
+                        <arg name="serial" type="uint"/>
+                        <arg name="surface" type="object" interface="wl_surface"/>
+                        <arg name="title" type="string" allow-null="true"/>
+                    </request>
+                </interface>
+            </protocol>"#,
+        )
+        .unwrap();
+        let requests = &protocol.interfaces[0].requests;
+
+        assert_eq!("usun", signature(requests[0].since, &requests[0].args));
+        assert_eq!("2uo?s", signature(requests[1].since, &requests[1].args));
+    }
+
+    #[test]
+    fn normalized_names() {
+        let protocol = parse_protocol_str(
+            r#"<protocol name="test">
+                <interface name="test_manager" version="1">
+                    <request name="set_mode"/>
+                    <request name="setMode"/>
+                    <event name="set_mode"/>
+                </interface>
+            </protocol>"#,
+        )
+        .unwrap();
+        let constants = interface_constants(&protocol.interfaces[0]).to_string();
+
+        for name in [
+            "REQ_SET_MODE_OPCODE",
+            "REQ_SET_MODE_2_OPCODE",
+            "REQ_SET_MODE_SINCE",
+            "SET_MODE_2_SINCE",
+            "EVT_SET_MODE_SINCE",
+        ] {
+            assert_eq!(
+                1,
+                constants.matches(&format!("const {name} ")).count(),
+                "{name}"
+            );
+        }
+    }
+}
@@ -0,0 +1,545 @@
+use std::fmt;
+
+use crate::{Arg, ArgType, Enum, EventType, Interface, Protocol, RequestType};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Item {
+    Interface,
+    Request,
+    Event,
+    Enum,
+    Entry,
+    Arg,
+}
+
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Interface => write!(f, "interface"),
+            Self::Request => write!(f, "request"),
+            Self::Event => write!(f, "event"),
+            Self::Enum => write!(f, "enum"),
+            Self::Entry => write!(f, "entry"),
+            Self::Arg => write!(f, "argument"),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ChangeKind {
+    Added(Item),
+    Removed(Item),
+    VersionChanged {
+        old: u32,
+        new: u32,
+    },
+    SinceChanged {
+        old: u32,
+        new: u32,
+    },
+    // An addition to an existing interface that is visible at a version the old
+    // protocol already had.
+    MissingSince {
+        since: u32,
+        expected: u32,
+    },
+    ArgTypeChanged {
+        old: String,
+        new: String,
+        wire: bool,
+    },
+    NullableChanged {
+        nullable: bool,
+    },
+    DestructorChanged {
+        destructor: bool,
+    },
+    BitfieldChanged {
+        bitfield: bool,
+    },
+    ValueChanged {
+        old: u32,
+        new: u32,
+    },
+    Deprecated {
+        since: u32,
+    },
+}
+
+pub struct Change {
+    location: String,
+    kind: ChangeKind,
+}
+
+impl Change {
+    pub fn get_location(&self) -> &str {
+        &self.location
+    }
+
+    pub fn get_kind(&self) -> &ChangeKind {
+        &self.kind
+    }
+
+    // Whether a peer built against the old protocol can misbehave when talking to one
+    // built against the new protocol.
+    pub fn is_breaking(&self) -> bool {
+        match &self.kind {
+            ChangeKind::Added(element) => *element == Item::Arg,
+            ChangeKind::Removed(_) => true,
+            ChangeKind::VersionChanged { old, new } => new < old,
+            ChangeKind::SinceChanged { .. } => true,
+            ChangeKind::MissingSince { .. } => true,
+            ChangeKind::ArgTypeChanged { wire, .. } => *wire,
+            ChangeKind::NullableChanged { nullable } => *nullable,
+            ChangeKind::DestructorChanged { .. } => true,
+            ChangeKind::BitfieldChanged { .. } => true,
+            ChangeKind::ValueChanged { .. } => true,
+            ChangeKind::Deprecated { .. } => false,
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.location)?;
+        match &self.kind {
+            ChangeKind::Added(element) => write!(f, "{element} added"),
+            ChangeKind::Removed(element) => write!(f, "{element} removed"),
+            ChangeKind::VersionChanged { old, new } => {
+                write!(f, "version changed from {old} to {new}")
+            }
+            ChangeKind::SinceChanged { old, new } => write!(f, "since changed from {old} to {new}"),
+            ChangeKind::MissingSince { since, expected } => {
+                write!(f, "added at since {since}, but needs since {expected}")
+            }
+            ChangeKind::ArgTypeChanged { old, new, .. } => {
+                write!(f, "type changed from `{old}` to `{new}`")
+            }
+            ChangeKind::NullableChanged { nullable: true } => write!(f, "became nullable"),
+            ChangeKind::NullableChanged { nullable: false } => write!(f, "is no longer nullable"),
+            ChangeKind::DestructorChanged { destructor: true } => write!(f, "became a destructor"),
+            ChangeKind::DestructorChanged { destructor: false } => {
+                write!(f, "is no longer a destructor")
+            }
+            ChangeKind::BitfieldChanged { bitfield: true } => write!(f, "became a bitfield"),
+            ChangeKind::BitfieldChanged { bitfield: false } => {
+                write!(f, "is no longer a bitfield")
+            }
+            ChangeKind::ValueChanged { old, new } => write!(f, "value changed from {old} to {new}"),
+            ChangeKind::Deprecated { since } => write!(f, "deprecated since {since}"),
+        }
+    }
+}
+
+pub fn diff(old: &Protocol, new: &Protocol) -> Vec<Change> {
+    let mut changes = Vec::<Change>::new();
+
+    for old_interface in &old.interfaces {
+        match new.interfaces.iter().find(|i| i.name == old_interface.name) {
+            Some(new_interface) => diff_interface(&mut changes, old_interface, new_interface),
+            None => push(
+                &mut changes,
+                &old_interface.name,
+                ChangeKind::Removed(Item::Interface),
+            ),
+        }
+    }
+    for new_interface in &new.interfaces {
+        if !old.interfaces.iter().any(|i| i.name == new_interface.name) {
+            push(
+                &mut changes,
+                &new_interface.name,
+                ChangeKind::Added(Item::Interface),
+            );
+        }
+    }
+
+    changes
+}
+
+fn push(changes: &mut Vec<Change>, location: &str, kind: ChangeKind) {
+    changes.push(Change {
+        location: location.to_string(),
+        kind,
+    });
+}
+
+fn diff_interface(changes: &mut Vec<Change>, old: &Interface, new: &Interface) {
+    if old.max_version != new.max_version {
+        push(
+            changes,
+            &old.name,
+            ChangeKind::VersionChanged {
+                old: old.max_version,
+                new: new.max_version,
+            },
+        );
+    }
+
+    let old_requests = old
+        .requests
+        .iter()
+        .map(|r| Message {
+            name: &r.name,
+            since: r.since,
+            deprecated_since: r.deprecated_since,
+            destructor: matches!(r.r#type, RequestType::Destructor),
+            args: &r.args,
+        })
+        .collect::<Vec<Message>>();
+    let new_requests = new
+        .requests
+        .iter()
+        .map(|r| Message {
+            name: &r.name,
+            since: r.since,
+            deprecated_since: r.deprecated_since,
+            destructor: matches!(r.r#type, RequestType::Destructor),
+            args: &r.args,
+        })
+        .collect::<Vec<Message>>();
+    diff_messages(changes, old, Item::Request, &old_requests, &new_requests);
+
+    let old_events = old
+        .events
+        .iter()
+        .map(|e| Message {
+            name: &e.name,
+            since: e.since,
+            deprecated_since: e.deprecated_since,
+            destructor: matches!(e.r#type, EventType::Destructor),
+            args: &e.args,
+        })
+        .collect::<Vec<Message>>();
+    let new_events = new
+        .events
+        .iter()
+        .map(|e| Message {
+            name: &e.name,
+            since: e.since,
+            deprecated_since: e.deprecated_since,
+            destructor: matches!(e.r#type, EventType::Destructor),
+            args: &e.args,
+        })
+        .collect::<Vec<Message>>();
+    diff_messages(changes, old, Item::Event, &old_events, &new_events);
+
+    for old_enum in &old.enums {
+        let location = format!("{}.{}", old.name, old_enum.name);
+        match new.enums.iter().find(|e| e.name == old_enum.name) {
+            Some(new_enum) => diff_enum(changes, &location, old.max_version, old_enum, new_enum),
+            None => push(changes, &location, ChangeKind::Removed(Item::Enum)),
+        }
+    }
+    for new_enum in &new.enums {
+        if !old.enums.iter().any(|e| e.name == new_enum.name) {
+            let location = format!("{}.{}", old.name, new_enum.name);
+            push(changes, &location, ChangeKind::Added(Item::Enum));
+            // Enums are not sent over the wire on their own, so an enum without a since
+            // only matters once an argument refers to it, which is covered there.
+        }
+    }
+}
+
+struct Message<'a> {
+    name: &'a str,
+    since: u32,
+    deprecated_since: Option<u32>,
+    destructor: bool,
+    args: &'a [Arg],
+}
+
+// Messages are matched by name, but the opcode is their position, so a message that
+// moved is as breaking as one that was removed.
+fn diff_messages(
+    changes: &mut Vec<Change>,
+    interface: &Interface,
+    element: Item,
+    old: &[Message],
+    new: &[Message],
+) {
+    for (opcode, old_message) in old.iter().enumerate() {
+        let location = format!("{}.{}", interface.name, old_message.name);
+        let Some(new_opcode) = new.iter().position(|m| m.name == old_message.name) else {
+            push(changes, &location, ChangeKind::Removed(element));
+            continue;
+        };
+        let new_message = &new[new_opcode];
+
+        if new_opcode != opcode {
+            push(changes, &location, ChangeKind::Removed(element));
+            push(changes, &location, ChangeKind::Added(element));
+            continue;
+        }
+        if old_message.since != new_message.since {
+            push(
+                changes,
+                &location,
+                ChangeKind::SinceChanged {
+                    old: old_message.since,
+                    new: new_message.since,
+                },
+            );
+        }
+        if old_message.destructor != new_message.destructor {
+            push(
+                changes,
+                &location,
+                ChangeKind::DestructorChanged {
+                    destructor: new_message.destructor,
+                },
+            );
+        }
+        if let (None, Some(since)) = (old_message.deprecated_since, new_message.deprecated_since) {
+            push(changes, &location, ChangeKind::Deprecated { since });
+        }
+        diff_args(changes, &location, old_message.args, new_message.args);
+    }
+
+    for new_message in new {
+        if old.iter().any(|m| m.name == new_message.name) {
+            continue;
+        }
+        let location = format!("{}.{}", interface.name, new_message.name);
+        push(changes, &location, ChangeKind::Added(element));
+        if new_message.since <= interface.max_version {
+            push(
+                changes,
+                &location,
+                ChangeKind::MissingSince {
+                    since: new_message.since,
+                    expected: interface.max_version + 1,
+                },
+            );
+        }
+    }
+}
+
+fn diff_args(changes: &mut Vec<Change>, location: &str, old: &[Arg], new: &[Arg]) {
+    for (old_arg, new_arg) in old.iter().zip(new) {
+        let location = format!("{location}.{}", new_arg.name);
+        if old_arg.r#type != new_arg.r#type {
+            push(
+                changes,
+                &location,
+                ChangeKind::ArgTypeChanged {
+                    old: old_arg.r#type.to_string(),
+                    new: new_arg.r#type.to_string(),
+                    wire: !compatible_types(&old_arg.r#type, &new_arg.r#type),
+                },
+            );
+        }
+        if old_arg.nullable != new_arg.nullable {
+            push(
+                changes,
+                &location,
+                ChangeKind::NullableChanged {
+                    nullable: new_arg.nullable,
+                },
+            );
+        }
+    }
+
+    for old_arg in old.iter().skip(new.len()) {
+        let location = format!("{location}.{}", old_arg.name);
+        push(changes, &location, ChangeKind::Removed(Item::Arg));
+    }
+    for new_arg in new.iter().skip(old.len()) {
+        let location = format!("{location}.{}", new_arg.name);
+        push(changes, &location, ChangeKind::Added(Item::Arg));
+    }
+}
+
+// Attaching an enum to a plain integer, or changing which enum it refers to, keeps the
+// wire format intact. Objects of another interface do not.
+fn compatible_types(old: &ArgType, new: &ArgType) -> bool {
+    match (old, new) {
+        (ArgType::Object(_), ArgType::Object(_)) | (ArgType::NewId(_), ArgType::NewId(_)) => false,
+        _ => old.wire_type() == new.wire_type(),
+    }
+}
+
+fn diff_enum(changes: &mut Vec<Change>, location: &str, max_version: u32, old: &Enum, new: &Enum) {
+    if old.is_bitfield != new.is_bitfield {
+        push(
+            changes,
+            location,
+            ChangeKind::BitfieldChanged {
+                bitfield: new.is_bitfield,
+            },
+        );
+    }
+
+    for old_entry in &old.entries {
+        let location = format!("{location}.{}", old_entry.name);
+        match new.entries.iter().find(|e| e.name == old_entry.name) {
+            Some(new_entry) => {
+                if old_entry.value != new_entry.value {
+                    push(
+                        changes,
+                        &location,
+                        ChangeKind::ValueChanged {
+                            old: old_entry.value,
+                            new: new_entry.value,
+                        },
+                    );
+                }
+                if let (None, Some(since)) =
+                    (old_entry.deprecated_since, new_entry.deprecated_since)
+                {
+                    push(changes, &location, ChangeKind::Deprecated { since });
+                }
+            }
+            None => push(changes, &location, ChangeKind::Removed(Item::Entry)),
+        }
+    }
+    for new_entry in &new.entries {
+        if old.entries.iter().any(|e| e.name == new_entry.name) {
+            continue;
+        }
+        let location = format!("{location}.{}", new_entry.name);
+        push(changes, &location, ChangeKind::Added(Item::Entry));
+        if new_entry.since <= max_version {
+            push(
+                changes,
+                &location,
+                ChangeKind::MissingSince {
+                    since: new_entry.since,
+                    expected: max_version + 1,
+                },
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChangeKind, Item, diff};
+    use crate::parse_protocol_str;
+
+    const OLD: &str = r#"<protocol name="test">
+        <interface name="test_manager" version="1">
+            <request name="get_thing">
+                <arg name="id" type="new_id" interface="test_thing"/>
+                <arg name="mode" type="uint"/>
+            </request>
+            <enum name="mode">
+                <entry name="on" value="1"/>
+            </enum>
+        </interface>
+    </protocol>"#;
+
+    #[test]
+    fn compatible() {
+        let new = r#"<protocol name="test">
+            <interface name="test_manager" version="2">
+                <request name="get_thing">
+                    <arg name="id" type="new_id" interface="test_thing"/>
+                    <arg name="mode" type="uint" enum="mode"/>
+                </request>
+                <request name="destroy" type="destructor" since="2"/>
+                <enum name="mode">
+                    <entry name="on" value="1"/>
+                    <entry name="off" value="2" since="2"/>
+                </enum>
+            </interface>
+        </protocol>"#;
+        let changes = diff(
+            &parse_protocol_str(OLD).unwrap(),
+            &parse_protocol_str(new).unwrap(),
+        );
+
+        assert_eq!(4, changes.len());
+        assert!(changes.iter().all(|c| !c.is_breaking()));
+    }
+
+    #[test]
+    fn breaking() {
+        let new = r#"<protocol name="test">
+            <interface name="test_manager" version="1">
+                <request name="get_thing">
+                    <arg name="id" type="new_id" interface="test_other"/>
+                    <arg name="mode" type="uint"/>
+                </request>
+                <enum name="mode">
+                    <entry name="on" value="1"/>
+                    <entry name="off" value="2"/>
+                </enum>
+            </interface>
+        </protocol>"#;
+        let changes = diff(
+            &parse_protocol_str(OLD).unwrap(),
+            &parse_protocol_str(new).unwrap(),
+        );
+        let kinds = changes
+            .iter()
+            .filter(|c| c.is_breaking())
+            .map(|c| c.get_kind().clone())
+            .collect::<Vec<ChangeKind>>();
+
+        assert_eq!(
+            vec![
+                ChangeKind::ArgTypeChanged {
+                    old: "new_id test_thing".to_string(),
+                    new: "new_id test_other".to_string(),
+                    wire: true,
+                },
+                ChangeKind::MissingSince {
+                    since: 1,
+                    expected: 2,
+                },
+            ],
+            kinds
+        );
+        assert!(
+            changes
+                .iter()
+                .any(|c| c.get_kind() == &ChangeKind::Added(Item::Entry))
+        );
+    }
+
+    #[test]
+    fn inserted() {
+        let old = r#"<protocol name="test">
+            <interface name="test_manager" version="1">
+                <request name="get_thing"/>
+                <request name="destroy" type="destructor"/>
+            </interface>
+        </protocol>"#;
+        let new = r#"<protocol name="test">
+            <interface name="test_manager" version="2">
+                <request name="get_thing"/>
+                <request name="reset"/>
+                <request name="destroy" type="destructor"/>
+            </interface>
+        </protocol>"#;
+        let changes = diff(
+            &parse_protocol_str(old).unwrap(),
+            &parse_protocol_str(new).unwrap(),
+        );
+        let changes = changes
+            .iter()
+            .map(|c| (c.get_location(), c.get_kind().clone()))
+            .collect::<Vec<(&str, ChangeKind)>>();
+
+        assert_eq!(
+            vec![
+                (
+                    "test_manager",
+                    ChangeKind::VersionChanged { old: 1, new: 2 }
+                ),
+                ("test_manager.destroy", ChangeKind::Removed(Item::Request)),
+                ("test_manager.destroy", ChangeKind::Added(Item::Request)),
+                ("test_manager.reset", ChangeKind::Added(Item::Request)),
+                (
+                    "test_manager.reset",
+                    ChangeKind::MissingSince {
+                        since: 1,
+                        expected: 2,
+                    },
+                ),
+            ],
+            changes
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use std::path::{Component, Path};
+
+// The collections protocols are distributed in. wayland-protocols is split by
+// stability, the others are taken as a whole.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Family {
+    Core,
+    Stable,
+    Staging,
+    Unstable,
+    Experimental,
+    Wlr,
+    Kde,
+}
+
+impl Family {
+    // Told apart by the directories of the installed XML, e.g.
+    // `wayland-protocols/staging/...`. Files outside of the known collections count as
+    // `Core`, those in a directory of wayland-protocols that is not known have none.
+    pub fn of(path: &Path) -> Option<Self> {
+        let components = path
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(name) => name.to_str(),
+                _ => None,
+            })
+            .collect::<Vec<&str>>();
+
+        if components.contains(&"wlr-protocols") {
+            return Some(Self::Wlr);
+        }
+        if components.contains(&"plasma-wayland-protocols") {
+            return Some(Self::Kde);
+        }
+        let Some(i) = components.iter().position(|c| *c == "wayland-protocols") else {
+            return Some(Self::Core);
+        };
+        match components.get(i + 1) {
+            Some(&"stable") => Some(Self::Stable),
+            Some(&"staging") => Some(Self::Staging),
+            Some(&"unstable") => Some(Self::Unstable),
+            Some(&"experimental") => Some(Self::Experimental),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::Family;
+
+    #[test]
+    fn of() {
+        let family = |path: &str| Family::of(Path::new(path));
+
+        assert_eq!(Some(Family::Core), family("/usr/share/wayland/wayland.xml"));
+        assert_eq!(
+            Some(Family::Stable),
+            family("/usr/share/wayland-protocols/stable/xdg-shell/xdg-shell.xml")
+        );
+        assert_eq!(
+            Some(Family::Staging),
+            family("/usr/share/wayland-protocols/staging/ext-idle-notify/ext-idle-notify-v1.xml")
+        );
+        assert_eq!(
+            Some(Family::Experimental),
+            family(
+                "/usr/share/wayland-protocols/experimental/xx-session-management/xx-session-management-v1.xml"
+            )
+        );
+        assert_eq!(
+            None,
+            family("/usr/share/wayland-protocols/deprecated/wl-shell.xml")
+        );
+        assert_eq!(
+            Some(Family::Wlr),
+            family("/usr/share/wlr-protocols/unstable/wlr-layer-shell-unstable-v1.xml")
+        );
+        assert_eq!(
+            Some(Family::Kde),
+            family("/usr/share/plasma-wayland-protocols/server-decoration.xml")
+        );
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Write;
+
+use crate::{Arg, ArgType, Protocol};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum EdgeKind {
+    // A `new_id` argument of the given interface.
+    Creates,
+    // An `object` argument of the given interface.
+    Refers,
+    // `wl_registry.bind` of a global.
+    Binds,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Edge {
+    from: String,
+    to: String,
+    kind: EdgeKind,
+    message: String,
+}
+
+impl Edge {
+    pub fn get_from(&self) -> &str {
+        &self.from
+    }
+
+    pub fn get_to(&self) -> &str {
+        &self.to
+    }
+
+    pub fn get_kind(&self) -> EdgeKind {
+        self.kind
+    }
+
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+pub struct Graph {
+    interfaces: Vec<String>,
+    edges: Vec<Edge>,
+}
+
+impl Graph {
+    // The XML does not say which interfaces are globals, so every interface that no
+    // message creates is assumed to be one, reachable through `wl_registry.bind`.
+    pub fn new(protocols: &[Protocol]) -> Self {
+        let mut interfaces = Vec::<String>::new();
+        let mut edges = Vec::<Edge>::new();
+        let mut seen = HashSet::<Edge>::new();
+        let mut push = |edge: Edge, edges: &mut Vec<Edge>| {
+            if seen.insert(edge.clone()) {
+                edges.push(edge);
+            }
+        };
+
+        for interface in protocols.iter().flat_map(|p| &p.interfaces) {
+            interfaces.push(interface.name.clone());
+
+            let messages = interface
+                .requests
+                .iter()
+                .map(|r| (&r.name, &r.args))
+                .chain(interface.events.iter().map(|e| (&e.name, &e.args)));
+            for (message, args) in messages {
+                for (to, kind) in args.iter().filter_map(reference) {
+                    let edge = Edge {
+                        from: interface.name.clone(),
+                        to: to.clone(),
+                        kind,
+                        message: message.clone(),
+                    };
+                    push(edge, &mut edges);
+                }
+            }
+        }
+
+        let created = edges
+            .iter()
+            .filter(|e| e.kind == EdgeKind::Creates)
+            .map(|e| e.to.clone())
+            .collect::<HashSet<String>>();
+        for interface in &interfaces {
+            if interface == "wl_display" || created.contains(interface) {
+                continue;
+            }
+            let edge = Edge {
+                from: "wl_registry".to_string(),
+                to: interface.clone(),
+                kind: EdgeKind::Binds,
+                message: "bind".to_string(),
+            };
+            push(edge, &mut edges);
+        }
+
+        Self { interfaces, edges }
+    }
+
+    pub fn get_interfaces(&self) -> &[String] {
+        &self.interfaces
+    }
+
+    pub fn get_edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    // The shortest chain of messages that creates an object of interface `to` from an
+    // object of interface `from`. Only `Creates` and `Binds` edges are followed.
+    pub fn path(&self, from: &str, to: &str) -> Option<Vec<&Edge>> {
+        let mut previous = HashMap::<&str, &Edge>::new();
+        let mut queue = VecDeque::<&str>::from([from]);
+
+        while let Some(interface) = queue.pop_front() {
+            if interface == to {
+                let mut path = Vec::<&Edge>::new();
+                let mut current = to;
+                while current != from {
+                    let edge = previous[current];
+                    path.push(edge);
+                    current = &edge.from;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            for edge in &self.edges {
+                if edge.from != interface
+                    || edge.kind == EdgeKind::Refers
+                    || edge.to == from
+                    || previous.contains_key(edge.to.as_str())
+                {
+                    continue;
+                }
+                previous.insert(&edge.to, edge);
+                queue.push_back(&edge.to);
+            }
+        }
+
+        None
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "digraph wayland {{").unwrap();
+        for interface in &self.interfaces {
+            writeln!(out, "    \"{interface}\";").unwrap();
+        }
+        for edge in &self.edges {
+            let style = match edge.kind {
+                EdgeKind::Creates => "solid",
+                EdgeKind::Refers => "dashed",
+                EdgeKind::Binds => "dotted",
+            };
+            writeln!(
+                out,
+                "    \"{}\" -> \"{}\" [label=\"{}\", style={style}];",
+                edge.from, edge.to, edge.message
+            )
+            .unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+        out
+    }
+
+    pub fn to_mermaid(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "graph LR").unwrap();
+        for interface in &self.interfaces {
+            writeln!(out, "    {interface}").unwrap();
+        }
+        for edge in &self.edges {
+            let arrow = match edge.kind {
+                EdgeKind::Creates => "-->",
+                EdgeKind::Refers => "-.->",
+                EdgeKind::Binds => "==>",
+            };
+            writeln!(
+                out,
+                "    {} {arrow}|{}| {}",
+                edge.from, edge.message, edge.to
+            )
+            .unwrap();
+        }
+        out
+    }
+}
+
+fn reference(arg: &Arg) -> Option<(&String, EdgeKind)> {
+    match &arg.r#type {
+        ArgType::NewId(interface) => Some((interface, EdgeKind::Creates)),
+        ArgType::Object(interface) => Some((interface, EdgeKind::Refers)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EdgeKind, Graph};
+    use crate::parse_protocol_str;
+
+    #[test]
+    fn path_from_registry() {
+        let protocol = parse_protocol_str(
+            r#"<protocol name="test">
+                <interface name="test_manager" version="1">
+                    <request name="get_thing">
+                        <arg name="id" type="new_id" interface="test_thing"/>
+                        <arg name="surface" type="object" interface="wl_surface"/>
+                    </request>
+                </interface>
+                <interface name="test_thing" version="1">
+                    <event name="child">
+                        <arg name="id" type="new_id" interface="test_child"/>
+                    </event>
+                </interface>
+                <interface name="test_child" version="1"/>
+            </protocol>"#,
+        )
+        .unwrap();
+        let graph = Graph::new(&[protocol]);
+        let path = graph
+            .path("wl_registry", "test_child")
+            .unwrap()
+            .iter()
+            .map(|e| (e.get_to(), e.get_kind(), e.get_message()))
+            .collect::<Vec<(&str, EdgeKind, &str)>>();
+
+        assert_eq!(
+            vec![
+                ("test_manager", EdgeKind::Binds, "bind"),
+                ("test_thing", EdgeKind::Creates, "get_thing"),
+                ("test_child", EdgeKind::Creates, "child"),
+            ],
+            path
+        );
+        assert!(graph.path("wl_registry", "wl_surface").is_none());
+    }
+}
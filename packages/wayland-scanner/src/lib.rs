@@ -1,17 +1,248 @@
 use std::env;
+use std::fmt;
 use std::fs::{self, File};
 use std::io::BufReader;
 use std::path::{Path, PathBuf};
 
-use serde::Deserialize;
+use client::GenClientTokens;
+use namespace::Namespace;
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use serde::{Deserialize, Serialize};
+use server::GenServerTokens;
+use syn::Ident;
+
+mod client;
+mod constants;
+mod diff;
+mod family;
+mod graph;
+mod namespace;
+mod naming;
+mod server;
+mod validate;
+mod xml;
+
+pub use diff::{Change, ChangeKind, Item, diff};
+pub use family::Family;
+pub use graph::{Edge, EdgeKind, Graph};
+pub use namespace::{Collision, External, ResolveError};
+pub use validate::{Issue, Severity, validate};
 
 pub fn parse_protocols() -> Vec<Protocol> {
-    collect_protocol_files()
+    collect_sources()
+        .files
         .iter()
-        .map(|f| parse_protocol(f))
+        .map(|f| parse_protocol(f).unwrap_or_else(|e| panic!("{}: {e}", f.display())))
         .collect::<Vec<Protocol>>()
 }
 
+pub fn generate_client_code(
+    protocols: &[Protocol],
+    externals: &[External],
+) -> Result<TokenStream, ResolveError> {
+    let namespace = Namespace::new(protocols, externals)?;
+    let generated = protocols
+        .iter()
+        .map(|p| GenClientTokens::to_tokens(p, &namespace))
+        .collect::<Vec<TokenStream>>();
+    // Brings the request methods of every interface into scope.
+    let modules = protocols
+        .iter()
+        .flat_map(|p| &p.interfaces)
+        .map(|i| {
+            let flat = namespace
+                .get(&i.name)
+                .expect("every parsed interface is registered in the namespace");
+            Ident::new(&flat.module, Span::call_site())
+        })
+        .collect::<Vec<Ident>>();
+
+    Ok(quote! {
+        pub mod wl {
+            #( #generated )*
+
+            pub mod prelude {
+                #( pub use super::#modules::Requests as _; )*
+            }
+        }
+    })
+}
+
+pub fn generate_server_code(
+    protocols: &[Protocol],
+    externals: &[External],
+) -> Result<TokenStream, ResolveError> {
+    let namespace = Namespace::new(protocols, externals)?;
+    let generated = protocols
+        .iter()
+        .map(|p| GenServerTokens::to_tokens(p, &namespace))
+        .collect::<Vec<TokenStream>>();
+
+    Ok(quote! {
+        pub mod wl {
+            #( #generated )*
+        }
+    })
+}
+
+// The code generator of one side of the connection, for the build script helpers.
+type Generator = fn(&[Protocol], &[External]) -> Result<TokenStream, ResolveError>;
+
+// Intended to be called from a build script, with the output pulled in through
+// `include!(concat!(env!("OUT_DIR"), "/<file_name>"))`. Generates the client code of the
+// installed protocols of the given families and returns them, e.g. for
+// `export_externals`.
+pub fn generate(
+    file_name: impl AsRef<Path>,
+    families: &[Family],
+    externals: &[External],
+) -> Result<Vec<Protocol>, GenerateError> {
+    generate_installed(file_name, families, externals, generate_client_code)
+}
+
+// Like `generate`, but for the server side.
+pub fn generate_server(
+    file_name: impl AsRef<Path>,
+    families: &[Family],
+    externals: &[External],
+) -> Result<Vec<Protocol>, GenerateError> {
+    generate_installed(file_name, families, externals, generate_server_code)
+}
+
+// Like `generate`, but for protocols that are not installed, such as private XML
+// shipped with the crate.
+pub fn generate_from(
+    file_name: impl AsRef<Path>,
+    files: &[impl AsRef<Path>],
+    externals: &[External],
+) -> Result<Vec<Protocol>, GenerateError> {
+    generate_files(file_name, files, externals, generate_client_code)
+}
+
+// Like `generate_from`, but for the server side.
+pub fn generate_server_from(
+    file_name: impl AsRef<Path>,
+    files: &[impl AsRef<Path>],
+    externals: &[External],
+) -> Result<Vec<Protocol>, GenerateError> {
+    generate_files(file_name, files, externals, generate_server_code)
+}
+
+fn generate_installed(
+    file_name: impl AsRef<Path>,
+    families: &[Family],
+    externals: &[External],
+    generator: Generator,
+) -> Result<Vec<Protocol>, GenerateError> {
+    let sources = collect_sources();
+
+    for var in &sources.env_vars {
+        println!("cargo:rerun-if-env-changed={var}");
+    }
+    for dir in &sources.dirs {
+        println!("cargo:rerun-if-changed={}", dir.display());
+    }
+
+    let files = sources
+        .files
+        .into_iter()
+        .filter(|f| Family::of(f).is_some_and(|family| families.contains(&family)))
+        .collect::<Vec<PathBuf>>();
+    generate_files(file_name, &files, externals, generator)
+}
+
+fn generate_files(
+    file_name: impl AsRef<Path>,
+    files: &[impl AsRef<Path>],
+    externals: &[External],
+    generator: Generator,
+) -> Result<Vec<Protocol>, GenerateError> {
+    let out_dir = env::var_os("OUT_DIR").ok_or(GenerateError::NoOutDir)?;
+
+    let mut protocols = Vec::<Protocol>::new();
+    for file in files {
+        let file = file.as_ref();
+        println!("cargo:rerun-if-changed={}", file.display());
+        let protocol =
+            parse_protocol(file).map_err(|e| GenerateError::Parse(file.to_path_buf(), e))?;
+        protocols.push(protocol);
+    }
+    let code = generator(&protocols, externals).map_err(GenerateError::Resolve)?;
+
+    fs::write(Path::new(&out_dir).join(file_name), code.to_string()).map_err(GenerateError::Io)?;
+    Ok(protocols)
+}
+
+// Makes the interfaces of `protocols`, found below `module`, available to the build
+// scripts of dependent crates through `import_externals`. The crate needs a `links`
+// key in its manifest for cargo to pass them on.
+pub fn export_externals(protocols: &[Protocol], module: &str) -> Result<(), GenerateError> {
+    let out_dir = env::var_os("OUT_DIR").ok_or(GenerateError::NoOutDir)?;
+    let externals = External::from_protocols(protocols, module).map_err(GenerateError::Resolve)?;
+
+    let mut contents = String::new();
+    for external in &externals {
+        let path = external.get_path();
+        let path = quote!(#path).to_string().replace(' ', "");
+        contents.push_str(&format!("{} {path}\n", external.get_interface()));
+    }
+    let path = Path::new(&out_dir).join("externals.txt");
+    fs::write(&path, contents).map_err(GenerateError::Io)?;
+
+    println!("cargo:externals={}", path.display());
+    Ok(())
+}
+
+// Reads the externals exported by the build script of `dependency`, which is the
+// `links` key of its manifest, e.g. `wayland-client-protocol`.
+pub fn import_externals(dependency: &str) -> Result<Vec<External>, GenerateError> {
+    let var = format!(
+        "DEP_{}_EXTERNALS",
+        dependency.to_ascii_uppercase().replace('-', "_")
+    );
+    println!("cargo:rerun-if-env-changed={var}");
+    let path = env::var(&var).map_err(|_| GenerateError::NoExternals(var))?;
+
+    fs::read_to_string(&path)
+        .map_err(GenerateError::Io)?
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(interface, path)| {
+            External::new(interface, path)
+                .map_err(|e| GenerateError::Resolve(ResolveError::InvalidPath(e)))
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+pub enum GenerateError {
+    NoOutDir,
+    Parse(PathBuf, ParseError),
+    Io(std::io::Error),
+    Resolve(ResolveError),
+    NoExternals(String),
+}
+
+impl fmt::Display for GenerateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoOutDir => write!(f, "OUT_DIR is not set, not running in a build script"),
+            Self::Parse(path, e) => write!(f, "{}: {e}", path.display()),
+            Self::Io(e) => write!(f, "{e}"),
+            Self::Resolve(e) => write!(f, "{e}"),
+            Self::NoExternals(var) => write!(
+                f,
+                "{var} is not set, is the crate a dependency with a `links` key?"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GenerateError {}
+
+// The JSON form of the model is described by protocol.schema.json.
+#[derive(Serialize, Deserialize)]
 pub struct Protocol {
     name: String,
     copyright: Option<Copyright>,
@@ -37,6 +268,7 @@ impl Protocol {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct Copyright(String);
 
 impl Copyright {
@@ -45,6 +277,7 @@ impl Copyright {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct Interface {
     name: String,
     max_version: u32,
@@ -80,6 +313,7 @@ impl Interface {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct Request {
     name: String,
     r#type: RequestType,
@@ -115,6 +349,7 @@ impl Request {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct Event {
     name: String,
     r#type: EventType,
@@ -150,6 +385,7 @@ impl Event {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct Enum {
     name: String,
     since: u32,
@@ -180,6 +416,7 @@ impl Enum {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct Entry {
     name: String,
     value: u32,
@@ -199,7 +436,7 @@ impl Entry {
     }
 
     pub fn get_summary(&self) -> Option<&str> {
-        self.summary.as_ref().map(String::as_str)
+        self.summary.as_deref()
     }
 
     pub fn get_since(&self) -> u32 {
@@ -215,6 +452,7 @@ impl Entry {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct Arg {
     name: String,
     r#type: ArgType,
@@ -233,7 +471,7 @@ impl Arg {
     }
 
     pub fn get_summary(&self) -> Option<&str> {
-        self.summary.as_ref().map(String::as_str)
+        self.summary.as_deref()
     }
 
     pub fn get_nullable(&self) -> bool {
@@ -245,6 +483,7 @@ impl Arg {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct Description {
     summary: String,
     content: Option<String>,
@@ -256,7 +495,7 @@ impl Description {
     }
 
     pub fn get_content(&self) -> Option<&str> {
-        self.content.as_ref().map(String::as_str)
+        self.content.as_deref()
     }
 }
 
@@ -280,7 +519,7 @@ struct RawInterface {
     #[serde(rename = "@version")]
     version: String,
     description: Option<RawDescription>,
-    #[serde(rename = "$value")]
+    #[serde(default, rename = "$value")]
     elements: Vec<Element>,
 }
 
@@ -375,50 +614,56 @@ struct RawDescription {
     content: Option<String>,
 }
 
-impl From<RawProtocol> for Protocol {
-    fn from(value: RawProtocol) -> Self {
-        Self {
-            name: if value.name == "wayland" {
-                "wl".to_string()
-            } else {
-                value.name
-            },
+impl TryFrom<RawProtocol> for Protocol {
+    type Error = ParseError;
+
+    fn try_from(value: RawProtocol) -> Result<Self, Self::Error> {
+        Ok(Self {
+            name: value.name,
             copyright: value.copyright.map(Copyright::from),
             description: value.description.map(Description::from),
-            interfaces: value.interfaces.into_iter().map(Interface::from).collect(),
-        }
+            interfaces: value
+                .interfaces
+                .into_iter()
+                .map(Interface::try_from)
+                .collect::<Result<_, _>>()?,
+        })
     }
 }
 
 impl From<RawCopyright> for Copyright {
     fn from(value: RawCopyright) -> Self {
-        Self(value.0.trim().to_string())
+        Self(dedent(&value.0))
     }
 }
 
-impl From<RawInterface> for Interface {
-    fn from(value: RawInterface) -> Self {
+impl TryFrom<RawInterface> for Interface {
+    type Error = ParseError;
+
+    fn try_from(value: RawInterface) -> Result<Self, Self::Error> {
         let mut requests = Vec::<Request>::new();
         let mut events = Vec::<Event>::new();
         let mut enums = Vec::<Enum>::new();
         for elem in value.elements {
             match elem {
-                Element::Request(req) => requests.push(req.into()),
-                Element::Event(ev) => events.push(ev.into()),
-                Element::Enum(en) => enums.push(en.into()),
+                Element::Request(req) => requests.push(req.try_into()?),
+                Element::Event(ev) => events.push(ev.try_into()?),
+                Element::Enum(en) => enums.push(en.try_into()?),
             };
         }
-        Self {
-            name: pascal_case(value.name.strip_prefix("wl_").unwrap_or(&value.name)),
-            max_version: value.version.parse().unwrap(),
+        Ok(Self {
+            name: value.name,
+            max_version: parse_number(&value.version)?,
             description: value.description.map(Description::from),
             requests,
             events,
             enums,
-        }
+        })
     }
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum RequestType {
     Default,
     Destructor,
@@ -433,19 +678,31 @@ impl From<Option<String>> for RequestType {
     }
 }
 
-impl From<RawRequest> for Request {
-    fn from(value: RawRequest) -> Self {
-        Self {
+impl TryFrom<RawRequest> for Request {
+    type Error = ParseError;
+
+    fn try_from(value: RawRequest) -> Result<Self, Self::Error> {
+        Ok(Self {
             name: value.name,
             r#type: value.r#type.into(),
-            since: value.since.map_or(1, |s| s.parse().unwrap()),
-            deprecated_since: value.deprecated_since.map(|s| s.parse().unwrap()),
+            since: value.since.as_deref().map_or(Ok(1), parse_number)?,
+            deprecated_since: value
+                .deprecated_since
+                .as_deref()
+                .map(parse_number)
+                .transpose()?,
             description: value.description.map(Description::from),
-            args: value.args.into_iter().map(Arg::from).collect(),
-        }
+            args: value
+                .args
+                .into_iter()
+                .map(Arg::try_from)
+                .collect::<Result<_, _>>()?,
+        })
     }
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum EventType {
     Default,
     Destructor,
@@ -460,97 +717,155 @@ impl From<Option<String>> for EventType {
     }
 }
 
-impl From<RawEvent> for Event {
-    fn from(value: RawEvent) -> Self {
-        Self {
-            name: pascal_case(value.name.as_str()),
+impl TryFrom<RawEvent> for Event {
+    type Error = ParseError;
+
+    fn try_from(value: RawEvent) -> Result<Self, Self::Error> {
+        Ok(Self {
+            name: value.name,
             r#type: value.r#type.into(),
-            since: value.since.map_or(1, |s| s.parse().unwrap()),
-            deprecated_since: value.deprecated_since.map(|s| s.parse().unwrap()),
+            since: value.since.as_deref().map_or(Ok(1), parse_number)?,
+            deprecated_since: value
+                .deprecated_since
+                .as_deref()
+                .map(parse_number)
+                .transpose()?,
             description: value.description.map(Description::from),
-            args: value.args.into_iter().map(Arg::from).collect(),
-        }
+            args: value
+                .args
+                .into_iter()
+                .map(Arg::try_from)
+                .collect::<Result<_, _>>()?,
+        })
     }
 }
 
-impl From<RawEnum> for Enum {
-    fn from(value: RawEnum) -> Self {
-        Self {
-            name: pascal_case(value.name.as_str()),
-            since: value.since.map_or(1, |s| s.parse().unwrap()),
-            is_bitfield: value.bitfield.map_or(false, |s| s == "true"),
+impl TryFrom<RawEnum> for Enum {
+    type Error = ParseError;
+
+    fn try_from(value: RawEnum) -> Result<Self, Self::Error> {
+        Ok(Self {
+            name: value.name,
+            since: value.since.as_deref().map_or(Ok(1), parse_number)?,
+            is_bitfield: value.bitfield.is_some_and(|s| s == "true"),
             description: value.description.map(Description::from),
-            entries: value.entries.into_iter().map(Entry::from).collect(),
-        }
+            entries: value
+                .entries
+                .into_iter()
+                .map(Entry::try_from)
+                .collect::<Result<_, _>>()?,
+        })
     }
 }
 
-impl From<RawEntry> for Entry {
-    fn from(value: RawEntry) -> Self {
-        let mut name = pascal_case(&value.name);
-        if syn::parse_str::<syn::Ident>(&value.name).is_err() {
-            name.insert(0, '_');
-        }
+impl TryFrom<RawEntry> for Entry {
+    type Error = ParseError;
 
-        Self {
-            name,
-            value: match value.value.strip_prefix("0x") {
-                Some(hex) => u32::from_str_radix(hex, 16).unwrap(),
-                _ => u32::from_str_radix(&value.value, 10).unwrap(),
-            },
+    fn try_from(value: RawEntry) -> Result<Self, Self::Error> {
+        Ok(Self {
+            name: value.name,
+            value: parse_number(&value.value)?,
             summary: value.summary,
-            since: value.since.map_or(1, |s| s.parse().unwrap()),
-            deprecated_since: value.deprecated_since.map(|s| s.parse().unwrap()),
+            since: value.since.as_deref().map_or(Ok(1), parse_number)?,
+            deprecated_since: value
+                .deprecated_since
+                .as_deref()
+                .map(parse_number)
+                .transpose()?,
             description: value.description.map(Description::from),
-        }
+        })
     }
 }
 
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ArgType {
     Int,
+    #[serde(rename = "uint")]
     UInt,
+    IntEnum(String),
     Enum(String),
     Fixed,
     String,
     Object(String),
+    UnspecifiedObject,
     NewId(String),
     UnspecifiedNewId,
     Array,
     Fd,
 }
 
-impl From<(String, Option<String>, Option<String>)> for ArgType {
-    fn from(value: (String, Option<String>, Option<String>)) -> Self {
+impl ArgType {
+    // The type as it travels over the wire, using libwayland's signature characters.
+    pub fn wire_type(&self) -> char {
+        match self {
+            Self::Int | Self::IntEnum(_) => 'i',
+            Self::UInt | Self::Enum(_) => 'u',
+            Self::Fixed => 'f',
+            Self::String => 's',
+            Self::Object(_) | Self::UnspecifiedObject => 'o',
+            Self::NewId(_) | Self::UnspecifiedNewId => 'n',
+            Self::Array => 'a',
+            Self::Fd => 'h',
+        }
+    }
+}
+
+impl fmt::Display for ArgType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Int => write!(f, "int"),
+            Self::UInt => write!(f, "uint"),
+            Self::IntEnum(en) => write!(f, "int enum {en}"),
+            Self::Enum(en) => write!(f, "uint enum {en}"),
+            Self::Fixed => write!(f, "fixed"),
+            Self::String => write!(f, "string"),
+            Self::Object(interface) => write!(f, "object {interface}"),
+            Self::UnspecifiedObject => write!(f, "object"),
+            Self::NewId(interface) => write!(f, "new_id {interface}"),
+            Self::UnspecifiedNewId => write!(f, "new_id"),
+            Self::Array => write!(f, "array"),
+            Self::Fd => write!(f, "fd"),
+        }
+    }
+}
+
+impl TryFrom<(String, Option<String>, Option<String>)> for ArgType {
+    type Error = ParseError;
+
+    fn try_from(value: (String, Option<String>, Option<String>)) -> Result<Self, Self::Error> {
         let t = value.0;
         let interface = value.1;
         let en = value.2;
 
-        match t.as_str() {
-            "int" => en.map_or(Self::Int, Self::Enum),
+        Ok(match t.as_str() {
+            "int" => en.map_or(Self::Int, Self::IntEnum),
             "uint" => en.map_or(Self::UInt, Self::Enum),
             "fixed" => Self::Fixed,
             "string" => Self::String,
-            "object" => interface.map_or(Self::UInt, Self::Object),
+            "object" => interface.map_or(Self::UnspecifiedObject, Self::Object),
             "new_id" => interface.map_or(Self::UnspecifiedNewId, Self::NewId),
             "array" => Self::Array,
             "fd" => Self::Fd,
-            _ => unreachable!(),
-        }
+            _ => return Err(ParseError::UnknownArgType(t)),
+        })
     }
 }
 
-impl From<RawArg> for Arg {
-    fn from(value: RawArg) -> Self {
-        Self {
+impl TryFrom<RawArg> for Arg {
+    type Error = ParseError;
+
+    fn try_from(value: RawArg) -> Result<Self, Self::Error> {
+        Ok(Self {
             name: value.name,
-            r#type: (value.r#type, value.interface, value.r#enum).into(),
+            r#type: (value.r#type, value.interface, value.r#enum).try_into()?,
             summary: value.summary,
             nullable: value
                 .allow_null
                 .as_deref()
-                .map_or(false, |s| s.eq_ignore_ascii_case("true")),
+                .is_some_and(|s| s.eq_ignore_ascii_case("true")),
             description: value.description.map(Description::from),
-        }
+        })
     }
 }
 
@@ -558,33 +873,54 @@ impl From<RawDescription> for Description {
     fn from(value: RawDescription) -> Self {
         Self {
             summary: value.summary,
-            content: value.content.map(|s| s.trim().to_string()),
+            content: value.content.map(|s| dedent(&s)),
         }
     }
 }
 
-// Thank you Chat GPT
-fn pascal_case(snake: &str) -> String {
-    snake
-        .split('_')
-        .filter(|part| !part.is_empty())
-        .map(|part| {
-            let mut chars = part.chars();
-            match chars.next() {
-                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
-                None => String::new(),
-            }
+// XML text keeps the indentation of the element it sits in. Once trimmed, the first
+// line has lost that indentation while the others still carry it, so the common
+// indentation of the remaining lines is stripped as well.
+fn dedent(text: &str) -> String {
+    let text = text.trim();
+    let common = text
+        .lines()
+        .skip(1)
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start().len())
+        .min()
+        .unwrap_or(0);
+
+    text.lines()
+        .enumerate()
+        .map(|(i, l)| match i {
+            0 => l.trim_end(),
+            _ => l.get(common..).unwrap_or("").trim_end(),
         })
-        .collect()
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+struct Sources {
+    dirs: Vec<PathBuf>,
+    files: Vec<PathBuf>,
+    env_vars: Vec<&'static str>,
 }
 
-fn collect_protocol_files() -> Vec<PathBuf> {
-    let dirs = get_paths();
-    let mut paths = Vec::<PathBuf>::new();
-    for dir in dirs {
-        collect_protocols_helper(dir.as_path(), &mut paths);
+fn collect_sources() -> Sources {
+    let mut env_vars = Vec::<&'static str>::new();
+    let dirs = get_paths(&mut env_vars);
+    let mut files = Vec::<PathBuf>::new();
+    for dir in &dirs {
+        collect_protocols_helper(dir.as_path(), &mut files);
+    }
+    // `read_dir` order is unspecified, and the generated code should not depend on it.
+    files.sort();
+    Sources {
+        dirs,
+        files,
+        env_vars,
     }
-    paths
 }
 
 fn collect_protocols_helper(dir: &Path, files: &mut Vec<PathBuf>) {
@@ -592,47 +928,143 @@ fn collect_protocols_helper(dir: &Path, files: &mut Vec<PathBuf>) {
         let path = entry.unwrap().path();
         if path.is_dir() {
             collect_protocols_helper(&path, files);
-        } else if path.is_file() {
-            if let Some(ext) = path.extension().and_then(|s| s.to_str()) {
-                if ext.eq_ignore_ascii_case("xml") {
-                    files.push(path);
-                }
-            }
+        } else if path.is_file()
+            && let Some(ext) = path.extension().and_then(|s| s.to_str())
+            && ext.eq_ignore_ascii_case("xml")
+        {
+            files.push(path);
         }
     }
 }
 
-fn get_paths() -> Vec<PathBuf> {
-    if let Ok(path) = env::var("WAYLAND_PROTOCOLS_PATH") {
+// Every variable that is looked at gets recorded, set or not, so that a build script
+// reruns when one that used to be unset appears.
+fn read_env(name: &'static str, env_vars: &mut Vec<&'static str>) -> Option<String> {
+    env_vars.push(name);
+    env::var(name).ok()
+}
+
+fn get_paths(env_vars: &mut Vec<&'static str>) -> Vec<PathBuf> {
+    if let Some(path) = read_env("WAYLAND_PROTOCOLS_PATH", env_vars) {
         return path.split(':').map(PathBuf::from).collect();
     }
-    if let Ok(path) = env::var("XDG_DATA_HOME") {
+    if let Some(path) = read_env("XDG_DATA_HOME", env_vars) {
         let path = PathBuf::from(path);
         if path.join("wayland").is_dir() && path.join("wayland-protocols").is_dir() {
-            return vec![path.join("wayland"), path.join("wayland-protocols")];
+            return data_dirs(&path);
         }
     }
-    if let Ok(path) = env::var("XDG_DATA_DIRS") {
-        if let Some(p) = path
+    if let Some(path) = read_env("XDG_DATA_DIRS", env_vars)
+        && let Some(p) = path
             .split(':')
             .map(Path::new)
             .find(|base| base.join("wayland").is_dir() && base.join("wayland-protocols").is_dir())
-            .map(Path::to_path_buf)
-        {
-            return vec![p.join("wayland"), p.join("wayland-protocols")];
-        }
+    {
+        return data_dirs(p);
     }
     match fs::exists("/usr/share") {
-        Ok(true) => vec![
-            PathBuf::from("/usr/share/wayland"),
-            PathBuf::from("/usr/share/wayland-protocols"),
-        ],
+        Ok(true) => data_dirs(Path::new("/usr/share")),
         _ => Vec::new(),
     }
 }
 
-fn parse_protocol(path: &Path) -> Protocol {
-    let file = File::open(path).unwrap();
+// wlr-protocols and plasma-wayland-protocols are optional, as few systems have them.
+fn data_dirs(base: &Path) -> Vec<PathBuf> {
+    let mut dirs = vec![base.join("wayland"), base.join("wayland-protocols")];
+    for optional in ["wlr-protocols", "plasma-wayland-protocols"] {
+        if base.join(optional).is_dir() {
+            dirs.push(base.join(optional));
+        }
+    }
+    dirs
+}
+
+pub fn parse_protocol(path: &Path) -> Result<Protocol, ParseError> {
+    let file = File::open(path).map_err(ParseError::Io)?;
     let reader = BufReader::new(file);
-    Protocol::from(quick_xml::de::from_reader::<_, RawProtocol>(reader).unwrap())
+    quick_xml::de::from_reader::<_, RawProtocol>(reader)
+        .map_err(ParseError::Xml)?
+        .try_into()
+}
+
+pub fn parse_protocol_str(xml: &str) -> Result<Protocol, ParseError> {
+    quick_xml::de::from_str::<RawProtocol>(xml)
+        .map_err(ParseError::Xml)?
+        .try_into()
+}
+
+fn parse_number(value: &str) -> Result<u32, ParseError> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        _ => value.parse(),
+    }
+    .map_err(|_| ParseError::InvalidNumber(value.to_string()))
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    Io(std::io::Error),
+    Xml(quick_xml::DeError),
+    InvalidNumber(String),
+    UnknownArgType(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{e}"),
+            Self::Xml(e) => write!(f, "malformed protocol XML: {e}"),
+            Self::InvalidNumber(value) => write!(f, "`{value}` is not a valid number"),
+            Self::UnknownArgType(t) => write!(f, "unknown argument type `{t}`"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{generate_from, generate_server_from, import_externals};
+
+    // The only test that touches the environment, as build scripts get their input
+    // through it.
+    #[test]
+    fn private_protocol() {
+        let dir = std::env::temp_dir().join(format!("wayland-scanner-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let externals = dir.join("externals.txt");
+        fs::write(
+            &externals,
+            "wl_surface wayland_client_protocol::wl::Surface\n",
+        )
+        .unwrap();
+        let xml = dir.join("private.xml");
+        fs::write(
+            &xml,
+            r#"<protocol name="private">
+                <interface name="private_blur" version="1">
+                    <request name="set_surface">
+                        <arg name="surface" type="object" interface="wl_surface"/>
+                    </request>
+                </interface>
+            </protocol>"#,
+        )
+        .unwrap();
+        unsafe {
+            std::env::set_var("DEP_TEST_PROTOCOL_EXTERNALS", &externals);
+            std::env::set_var("OUT_DIR", &dir);
+        }
+
+        let externals = import_externals("test-protocol").unwrap();
+        assert_eq!(1, externals.len());
+        generate_from("private.rs", &[&xml], &externals).unwrap();
+        let code = fs::read_to_string(dir.join("private.rs")).unwrap();
+        assert!(code.contains("wayland_client_protocol :: wl :: Surface"));
+
+        generate_server_from("private_server.rs", &[&xml], &externals).unwrap();
+        let code = fs::read_to_string(dir.join("private_server.rs")).unwrap();
+        assert!(code.contains("resource :: Resource for PrivateBlur"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
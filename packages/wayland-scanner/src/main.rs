@@ -0,0 +1,190 @@
+use std::path::Path;
+use std::process::ExitCode;
+
+use wayland_scanner::{External, Graph, Protocol, ResolveError, Severity};
+
+const USAGE: &str = "\
+usage: wayland-scanner <command> <file>...
+       wayland-scanner path <interface> <file>...
+       wayland-scanner client|server [--extern <module>=<file>]... <file>...
+
+commands:
+    client      print the generated client code
+    server      print the generated server code
+    json        print the parsed protocols as JSON
+    xml         print the protocols as normalized XML
+    validate    check the protocols for mistakes
+    diff        compare an old and a new version of a protocol
+    dot         print the object creation graph in Graphviz DOT
+    mermaid     print the object creation graph in Mermaid
+    path        print how to create an object of <interface> from wl_registry
+
+--extern takes the interfaces of <file> from the Rust module <module>, e.g.
+`--extern wayland_client_protocol::wl=wayland.xml`, instead of generating them.";
+
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    let Some((command, mut files)) = args.split_first() else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+    let mut target = None;
+    if command == "path" {
+        let Some((interface, rest)) = files.split_first() else {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        };
+        target = Some(interface);
+        files = rest;
+    }
+    let mut externals = Vec::<External>::new();
+    while let [flag, value, rest @ ..] = files
+        && flag == "--extern"
+    {
+        let result = match value.split_once('=') {
+            Some((module, file)) => read_externals(module, file),
+            None => Err(format!("--extern expects <module>=<file>, got `{value}`")),
+        };
+        match result {
+            Ok(mut declared) => externals.append(&mut declared),
+            Err(e) => {
+                eprintln!("error: {e}");
+                return ExitCode::FAILURE;
+            }
+        }
+        files = rest;
+    }
+    if files.is_empty() {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    }
+
+    let mut protocols = Vec::<Protocol>::new();
+    for file in files {
+        match wayland_scanner::parse_protocol(Path::new(file)) {
+            Ok(protocol) => protocols.push(protocol),
+            Err(e) => {
+                eprintln!("error: {file}: {e}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    match command.as_str() {
+        "client" => print_code(wayland_scanner::generate_client_code(
+            &protocols, &externals,
+        )),
+        "server" => print_code(wayland_scanner::generate_server_code(
+            &protocols, &externals,
+        )),
+        "json" => {
+            println!("{}", serde_json::to_string_pretty(&protocols).unwrap());
+            ExitCode::SUCCESS
+        }
+        "xml" => {
+            for protocol in &protocols {
+                print!("{}", protocol.to_xml());
+            }
+            ExitCode::SUCCESS
+        }
+        "validate" => validate(&protocols),
+        "dot" => {
+            print!("{}", Graph::new(&protocols).to_dot());
+            ExitCode::SUCCESS
+        }
+        "mermaid" => {
+            print!("{}", Graph::new(&protocols).to_mermaid());
+            ExitCode::SUCCESS
+        }
+        "path" => path(&protocols, target.unwrap()),
+        "diff" => match protocols.as_slice() {
+            [old, new] => diff(old, new),
+            _ => {
+                eprintln!("{USAGE}");
+                ExitCode::FAILURE
+            }
+        },
+        _ => {
+            eprintln!("{USAGE}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn read_externals(module: &str, file: &str) -> Result<Vec<External>, String> {
+    let protocol =
+        wayland_scanner::parse_protocol(Path::new(file)).map_err(|e| format!("{file}: {e}"))?;
+    External::from_protocols(&[protocol], module).map_err(|e| e.to_string())
+}
+
+fn print_code(code: Result<proc_macro2::TokenStream, ResolveError>) -> ExitCode {
+    match code {
+        Ok(code) => {
+            let file = syn::parse2::<syn::File>(code).expect("generated code is valid Rust");
+            print!("{}", prettyplease::unparse(&file));
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn validate(protocols: &[Protocol]) -> ExitCode {
+    let issues = wayland_scanner::validate(protocols);
+    for issue in &issues {
+        println!("{issue}");
+    }
+
+    let errors = issues
+        .iter()
+        .filter(|i| i.get_severity() == Severity::Error)
+        .count();
+    let warnings = issues.len() - errors;
+    println!("{errors} error(s), {warnings} warning(s)");
+
+    if errors > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn diff(old: &Protocol, new: &Protocol) -> ExitCode {
+    let changes = wayland_scanner::diff(old, new);
+    for change in &changes {
+        if change.is_breaking() {
+            println!("breaking: {change}");
+        } else {
+            println!("compatible: {change}");
+        }
+    }
+
+    if changes.iter().any(|c| c.is_breaking()) {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn path(protocols: &[Protocol], interface: &str) -> ExitCode {
+    let graph = Graph::new(protocols);
+    match graph.path("wl_registry", interface) {
+        Some(path) => {
+            for edge in path {
+                println!(
+                    "{}.{} -> {}",
+                    edge.get_from(),
+                    edge.get_message(),
+                    edge.get_to()
+                );
+            }
+            ExitCode::SUCCESS
+        }
+        None => {
+            eprintln!("error: `{interface}` cannot be reached from wl_registry");
+            ExitCode::FAILURE
+        }
+    }
+}
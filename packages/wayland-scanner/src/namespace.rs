@@ -0,0 +1,315 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use proc_macro2::{Span, TokenStream};
+use quote::{ToTokens, quote};
+use syn::Ident;
+
+use crate::naming::type_name;
+use crate::{ArgType, Protocol};
+
+const CORE_PROTOCOL: &str = "wayland";
+const CORE_PREFIX: &str = "wl_";
+
+// The symbol table of the generated code: every interface that an argument can refer
+// to, either generated alongside it or supplied by another crate.
+pub struct Namespace {
+    names: HashMap<String, FlatName>,
+    externals: HashMap<String, syn::Path>,
+}
+
+pub struct FlatName {
+    pub module: String,
+    pub type_name: String,
+}
+
+// An interface whose type is defined outside of the generated code, typically by a crate
+// that generated it from another protocol, e.g. `wl_surface` from
+// `wayland_client_protocol::wl::Surface`.
+#[derive(Clone)]
+pub struct External {
+    interface: String,
+    path: syn::Path,
+}
+
+impl External {
+    pub fn new(interface: &str, path: &str) -> Result<Self, syn::Error> {
+        Ok(Self {
+            interface: interface.to_string(),
+            path: syn::parse_str::<syn::Path>(path)?,
+        })
+    }
+
+    // Declares every interface of `protocols` as external, named the way this crate
+    // names them, below `module`, e.g. `wayland_client_protocol::wl`.
+    pub fn from_protocols(protocols: &[Protocol], module: &str) -> Result<Vec<Self>, ResolveError> {
+        let namespace = Namespace::new(protocols, &[])?;
+        let mut externals = namespace
+            .names
+            .iter()
+            .map(|(interface, flat)| {
+                External::new(interface, &format!("{module}::{}", flat.type_name))
+            })
+            .collect::<Result<Vec<External>, syn::Error>>()
+            .map_err(ResolveError::InvalidPath)?;
+        externals.sort_by(|a, b| a.interface.cmp(&b.interface));
+        Ok(externals)
+    }
+
+    pub fn get_interface(&self) -> &str {
+        &self.interface
+    }
+
+    pub fn get_path(&self) -> &syn::Path {
+        &self.path
+    }
+}
+
+impl Namespace {
+    // Generated interfaces shadow externals of the same name.
+    pub fn new(protocols: &[Protocol], externals: &[External]) -> Result<Self, ResolveError> {
+        let mut names = HashMap::<String, FlatName>::new();
+        let mut modules = HashMap::<String, Origin>::new();
+        let mut types = HashMap::<String, Origin>::new();
+
+        for protocol in protocols {
+            for interface in &protocol.interfaces {
+                let origin = Origin {
+                    protocol: protocol.name.clone(),
+                    interface: interface.name.clone(),
+                };
+                let module = flat_module_name(&protocol.name, &interface.name);
+                let type_name = type_name(&module);
+
+                if let Some(first) = types.get(&type_name).or(modules.get(&module)) {
+                    return Err(ResolveError::Collision(Collision {
+                        name: type_name,
+                        first: first.clone(),
+                        second: origin,
+                    }));
+                }
+
+                modules.insert(module.clone(), origin.clone());
+                types.insert(type_name.clone(), origin);
+                names.insert(interface.name.clone(), FlatName { module, type_name });
+            }
+        }
+
+        let externals = externals
+            .iter()
+            .filter(|e| !names.contains_key(&e.interface))
+            .map(|e| (e.interface.clone(), e.path.clone()))
+            .collect::<HashMap<String, syn::Path>>();
+        let namespace = Self { names, externals };
+
+        for protocol in protocols {
+            for interface in &protocol.interfaces {
+                let messages = interface
+                    .requests
+                    .iter()
+                    .map(|r| (&r.name, &r.args))
+                    .chain(interface.events.iter().map(|e| (&e.name, &e.args)));
+                for (message, args) in messages {
+                    for arg in args {
+                        if let ArgType::Object(name) | ArgType::NewId(name) = &arg.r#type
+                            && namespace.resolve(name).is_none()
+                        {
+                            return Err(ResolveError::UnknownInterface {
+                                interface: name.clone(),
+                                location: format!("{}.{message}.{}", interface.name, arg.name),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(namespace)
+    }
+
+    pub fn get(&self, interface: &str) -> Option<&FlatName> {
+        self.names.get(interface)
+    }
+
+    // The path to the type of `interface`, as seen from inside the module of any
+    // generated interface.
+    pub fn resolve(&self, interface: &str) -> Option<TokenStream> {
+        if let Some(flat) = self.names.get(interface) {
+            let type_name = Ident::new(&flat.type_name, Span::call_site());
+            return Some(quote! { super::#type_name });
+        }
+        self.externals
+            .get(interface)
+            .map(|path| path.to_token_stream())
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Origin {
+    protocol: String,
+    interface: String,
+}
+
+#[derive(Debug)]
+pub struct Collision {
+    name: String,
+    first: Origin,
+    second: Origin,
+}
+
+impl fmt::Display for Collision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`wl::{}` is defined by both interface `{}` (protocol `{}`) and interface `{}` (protocol `{}`)",
+            self.name,
+            self.first.interface,
+            self.first.protocol,
+            self.second.interface,
+            self.second.protocol,
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum ResolveError {
+    Collision(Collision),
+    UnknownInterface { interface: String, location: String },
+    InvalidPath(syn::Error),
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Collision(collision) => write!(f, "{collision}"),
+            Self::UnknownInterface {
+                interface,
+                location,
+            } => write!(
+                f,
+                "`{location}` refers to interface `{interface}`, which is neither generated nor declared external"
+            ),
+            Self::InvalidPath(e) => write!(f, "invalid external path: {e}"),
+        }
+    }
+}
+
+// Only interfaces from the core protocol lose their prefix, so that a third party
+// `wl_foo` interface can never shadow or masquerade as a core type.
+fn flat_module_name(protocol: &str, interface: &str) -> String {
+    if protocol == CORE_PROTOCOL
+        && let Some(stripped) = interface.strip_prefix(CORE_PREFIX)
+    {
+        return stripped.to_string();
+    }
+    interface.trim_start_matches('_').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{External, Namespace, ResolveError};
+    use crate::{Interface, Protocol, parse_protocol_str};
+
+    fn protocol(name: &str, interfaces: &[&str]) -> Protocol {
+        Protocol {
+            name: name.to_string(),
+            copyright: None,
+            description: None,
+            interfaces: interfaces
+                .iter()
+                .map(|i| Interface {
+                    name: i.to_string(),
+                    max_version: 1,
+                    description: None,
+                    requests: Vec::new(),
+                    events: Vec::new(),
+                    enums: Vec::new(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn core_prefix_stripped() {
+        let protocols = [
+            protocol("wayland", &["wl_display", "wl_surface"]),
+            protocol("xdg_shell", &["xdg_toplevel"]),
+        ];
+        let namespace = Namespace::new(&protocols, &[]).ok().unwrap();
+
+        assert_eq!("Display", namespace.get("wl_display").unwrap().type_name);
+        assert_eq!("surface", namespace.get("wl_surface").unwrap().module);
+        assert_eq!(
+            "XdgToplevel",
+            namespace.get("xdg_toplevel").unwrap().type_name
+        );
+    }
+
+    #[test]
+    fn foreign_prefix_kept() {
+        let protocols = [protocol("text", &["wl_text_input", "_wl_fullscreen_shell"])];
+        let namespace = Namespace::new(&protocols, &[]).ok().unwrap();
+
+        assert_eq!(
+            "WlTextInput",
+            namespace.get("wl_text_input").unwrap().type_name
+        );
+        assert_eq!(
+            "WlFullscreenShell",
+            namespace.get("_wl_fullscreen_shell").unwrap().type_name
+        );
+    }
+
+    #[test]
+    fn collision() {
+        let protocols = [protocol("foo", &["manager"]), protocol("bar", &["manager"])];
+        let error = Namespace::new(&protocols, &[]).err().unwrap().to_string();
+
+        assert_eq!(
+            "`wl::Manager` is defined by both interface `manager` (protocol `foo`) and interface `manager` (protocol `bar`)",
+            error
+        );
+    }
+
+    #[test]
+    fn stripped_core_collision() {
+        let protocols = [protocol("wayland", &["wl_shm"]), protocol("foo", &["shm"])];
+
+        assert!(Namespace::new(&protocols, &[]).is_err());
+    }
+
+    #[test]
+    fn external_interfaces() {
+        let xdg_shell = parse_protocol_str(
+            r#"<protocol name="xdg_shell">
+                <interface name="xdg_wm_base" version="1">
+                    <request name="get_xdg_surface">
+                        <arg name="id" type="new_id" interface="xdg_surface"/>
+                        <arg name="surface" type="object" interface="wl_surface"/>
+                    </request>
+                </interface>
+                <interface name="xdg_surface" version="1"/>
+            </protocol>"#,
+        )
+        .unwrap();
+        let protocols = [xdg_shell];
+
+        let error = Namespace::new(&protocols, &[]).err().unwrap();
+        assert!(matches!(error, ResolveError::UnknownInterface { .. }));
+
+        let externals = External::from_protocols(
+            &[protocol("wayland", &["wl_surface"])],
+            "wayland_client_protocol::wl",
+        )
+        .unwrap();
+        let namespace = Namespace::new(&protocols, &externals).ok().unwrap();
+        assert_eq!(
+            "wayland_client_protocol :: wl :: Surface",
+            namespace.resolve("wl_surface").unwrap().to_string()
+        );
+        assert_eq!(
+            "super :: XdgSurface",
+            namespace.resolve("xdg_surface").unwrap().to_string()
+        );
+    }
+}
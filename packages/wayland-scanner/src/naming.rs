@@ -0,0 +1,191 @@
+use std::collections::HashSet;
+
+use proc_macro2::{Span, TokenStream};
+use quote::ToTokens;
+use syn::Ident;
+
+// Keywords that cannot be written as raw identifiers either.
+const NOT_RAW: &[&str] = &["_", "crate", "self", "Self", "super"];
+
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "do", "dyn",
+    "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl", "in", "let",
+    "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return",
+    "static", "struct", "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use",
+    "virtual", "where", "while", "yield",
+];
+
+// Splits a protocol name into lowercase words. Names are mostly snake_case, but some
+// protocols use CamelCase or all caps, e.g. `BypassWindowManager`, `EDID` or `sRGB`.
+// Digits stay attached to the word they follow, so `xrgb8888` and `flipped_90` keep
+// their number next to what it qualifies.
+fn words(name: &str) -> Vec<String> {
+    let mut words = Vec::<String>::new();
+    for part in name.split('_').filter(|p| !p.is_empty()) {
+        let mut current = String::new();
+        let mut previous_lower = false;
+        for c in part.chars() {
+            // A single leading lowercase letter is a prefix, as in `sRGB`.
+            if c.is_ascii_uppercase() && previous_lower && current.len() > 1 {
+                words.push(current.to_ascii_lowercase());
+                current.clear();
+            }
+            previous_lower = c.is_ascii_lowercase();
+            current.push(c);
+        }
+        words.push(current.to_ascii_lowercase());
+    }
+    words
+}
+
+// Acronyms are capitalized like any other word, as the Rust API guidelines ask, so
+// `dmabuf` becomes `Dmabuf` and `EDID` becomes `Edid`.
+pub(crate) fn type_name(name: &str) -> String {
+    let mut type_name = String::new();
+    for word in words(name) {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            type_name.push(first.to_ascii_uppercase());
+            type_name.push_str(chars.as_str());
+        }
+    }
+
+    if type_name.is_empty() || type_name.starts_with(|c: char| c.is_ascii_digit()) {
+        type_name.insert(0, '_');
+    }
+    if type_name == "Self" {
+        type_name.push('_');
+    }
+    type_name
+}
+
+// Names for functions, fields and modules, in snake_case.
+pub(crate) fn value_name(name: &str) -> String {
+    let mut value_name = words(name).join("_");
+    if value_name.is_empty() || value_name.starts_with(|c: char| c.is_ascii_digit()) {
+        value_name.insert(0, '_');
+    }
+    if NOT_RAW.contains(&value_name.as_str()) {
+        value_name.push('_');
+    }
+    value_name
+}
+
+pub(crate) fn value_ident(name: &str) -> TokenStream {
+    if KEYWORDS.contains(&name) {
+        Ident::new_raw(name, Span::call_site()).into_token_stream()
+    } else {
+        Ident::new(name, Span::call_site()).into_token_stream()
+    }
+}
+
+// Entries that are nothing but a number, like the `90` of `wl_output.transform`, are
+// named after the last word of their enum: `Transform90`.
+pub(crate) fn variant_names<'a>(
+    enum_name: &str,
+    entries: impl IntoIterator<Item = &'a str>,
+) -> Vec<String> {
+    let prefix = words(enum_name)
+        .pop()
+        .map(|w| type_name(&w))
+        .unwrap_or_default();
+    let names = entries
+        .into_iter()
+        .map(|entry| {
+            if entry.starts_with(|c: char| c.is_ascii_digit()) {
+                type_name(&format!("{prefix}{entry}"))
+            } else {
+                type_name(entry)
+            }
+        })
+        .collect::<Vec<String>>();
+    unique(names)
+}
+
+// Different names can convert to the same identifier, such as `a_b1` and `a_b_1`. The
+// first one keeps it and the others get a numbered suffix.
+pub(crate) fn unique(names: Vec<String>) -> Vec<String> {
+    let mut seen = names.iter().cloned().collect::<HashSet<String>>();
+    let mut first = HashSet::<String>::new();
+
+    names
+        .into_iter()
+        .map(|name| {
+            if first.insert(name.clone()) {
+                return name;
+            }
+            let mut n = 2;
+            loop {
+                let candidate = format!("{name}_{n}");
+                if seen.insert(candidate.clone()) {
+                    return candidate;
+                }
+                n += 1;
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use syn::Ident;
+
+    use super::{type_name, unique, value_ident, value_name, variant_names};
+    use crate::parse_protocols;
+
+    #[test]
+    fn names() {
+        assert_eq!("ZwpLinuxDmabufV1", type_name("zwp_linux_dmabuf_v1"));
+        assert_eq!("Xrgb8888", type_name("xrgb8888"));
+        assert_eq!("Flipped90", type_name("flipped_90"));
+        assert_eq!("BypassWindowManager", type_name("BypassWindowManager"));
+        assert_eq!("Edid", type_name("EDID"));
+        assert_eq!("Srgb", type_name("sRGB"));
+        assert_eq!("Self_", type_name("self"));
+        assert_eq!("r#type", value_ident(&value_name("type")).to_string());
+        assert_eq!("self_", value_name("self"));
+        assert_eq!(
+            vec!["Normal", "Transform90", "Flipped90"],
+            variant_names("transform", ["normal", "90", "flipped_90"])
+        );
+        assert_eq!(
+            vec!["AB1", "AB1_2"],
+            unique(vec!["AB1".to_string(), "AB1".to_string()])
+        );
+    }
+
+    // Runs over whatever protocols are installed, see `get_paths`.
+    #[test]
+    fn upstream_protocols() {
+        fn check(names: &[String]) {
+            let mut seen = HashSet::<&str>::new();
+            for name in names {
+                assert!(syn::parse_str::<Ident>(name).is_ok(), "{name}");
+                assert!(seen.insert(name), "{name}");
+            }
+        }
+
+        for protocol in parse_protocols() {
+            for interface in &protocol.interfaces {
+                let requests = interface.requests.iter().map(|r| value_name(&r.name));
+                let requests = unique(requests.collect())
+                    .iter()
+                    .map(|r| value_ident(r).to_string())
+                    .collect::<Vec<String>>();
+                check(&requests);
+                let events = interface.events.iter().map(|e| type_name(&e.name));
+                check(&unique(events.collect()));
+                let enums = interface.enums.iter().map(|e| type_name(&e.name));
+                check(&unique(enums.collect()));
+                for en in &interface.enums {
+                    check(&variant_names(
+                        &en.name,
+                        en.entries.iter().map(|e| e.name.as_str()),
+                    ));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,451 @@
+use crate::client::{GenClientTokens, arg_names};
+use crate::constants::{interface_constants, signature};
+use crate::namespace::Namespace;
+use crate::naming::{self, unique, value_ident, value_name};
+use crate::{Arg, ArgType, Event, EventType, Interface, Protocol, Request};
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::Ident;
+
+pub trait GenServerTokens {
+    fn to_tokens(self, namespace: &Namespace) -> TokenStream;
+}
+
+impl GenServerTokens for &Protocol {
+    fn to_tokens(self, namespace: &Namespace) -> TokenStream {
+        let interfaces = self
+            .interfaces
+            .iter()
+            .map(|i| GenServerTokens::to_tokens(i, namespace))
+            .collect::<Vec<TokenStream>>();
+
+        quote! {
+            #( #interfaces )*
+        }
+    }
+}
+
+impl GenServerTokens for &Interface {
+    fn to_tokens(self, namespace: &Namespace) -> TokenStream {
+        let flat = namespace
+            .get(&self.name)
+            .expect("every parsed interface is registered in the namespace");
+        let name = Ident::new(&flat.module, Span::call_site());
+        let type_name = Ident::new(&flat.type_name, Span::call_site());
+        let event_names = unique(self.events.iter().map(|e| value_name(&e.name)).collect());
+        let events = self
+            .events
+            .iter()
+            .zip(&event_names)
+            .enumerate()
+            .map(|(opcode, (e, name))| event_sender(e, name, opcode as u16, namespace))
+            .collect::<Vec<TokenStream>>();
+        let request_names = unique(
+            self.requests
+                .iter()
+                .map(|r| naming::type_name(&r.name) + "Request")
+                .collect(),
+        );
+        let requests = self
+            .requests
+            .iter()
+            .zip(&request_names)
+            .map(|(r, name)| GenServerTokens::to_tokens((r, name.as_str()), namespace))
+            .collect::<Vec<TokenStream>>();
+        // Enums are the same on both sides of the connection.
+        let enum_names = unique(
+            self.enums
+                .iter()
+                .map(|e| naming::type_name(&e.name))
+                .collect(),
+        );
+        let enums = self
+            .enums
+            .iter()
+            .zip(&enum_names)
+            .map(|(e, name)| GenClientTokens::to_tokens((e, name.as_str()), namespace))
+            .collect::<Vec<TokenStream>>();
+        let constants = interface_constants(self);
+        let interface = &self.name;
+        let max_version = self.max_version;
+        let request_messages = self
+            .requests
+            .iter()
+            .map(|r| message(&r.name, r.since, &r.args, namespace));
+        let event_messages = self
+            .events
+            .iter()
+            .map(|e| message(&e.name, e.since, &e.args, namespace));
+
+        quote! {
+            pub use #name::#type_name;
+
+            pub mod #name {
+                pub static INTERFACE: ::wayland_server_core::interface::Interface =
+                    ::wayland_server_core::interface::Interface {
+                        name: #interface,
+                        version: #max_version,
+                        requests: &[ #( #request_messages ),* ],
+                        events: &[ #( #event_messages ),* ],
+                    };
+
+                // Not `Clone`, so that a destructor event, which takes the resource by
+                // value, leaves nothing behind to send further events with.
+                #[derive(PartialEq, Eq, Hash, Debug)]
+                pub struct #type_name {
+                    id: u32,
+                }
+
+                impl ::wayland_server_core::resource::Resource for #type_name {
+                    const INTERFACE: &'static ::wayland_server_core::interface::Interface =
+                        &INTERFACE;
+
+                    fn from_id(id: u32) -> Self {
+                        Self { id }
+                    }
+
+                    fn get_id(&self) -> u32 {
+                        self.id
+                    }
+                }
+
+                #constants
+
+                impl #type_name {
+                    #( #events )*
+                }
+
+                #( #requests )*
+
+                #( #enums )*
+            }
+        }
+    }
+}
+
+// The statics of generated interfaces are referred to directly, as they may refer to
+// each other, or to themselves. Those of other crates through their resource types.
+fn interface_ref(interface: &str, namespace: &Namespace) -> TokenStream {
+    if let Some(flat) = namespace.get(interface) {
+        let module = Ident::new(&flat.module, Span::call_site());
+        return quote! { &super::#module::INTERFACE };
+    }
+    let path = namespace
+        .resolve(interface)
+        .expect("references are checked when the namespace is built");
+    quote! { <#path as ::wayland_server_core::resource::Resource>::INTERFACE }
+}
+
+fn message(name: &str, since: u32, args: &[Arg], namespace: &Namespace) -> TokenStream {
+    let signature = signature(since, args);
+    let mut types = Vec::<TokenStream>::new();
+    for arg in args {
+        match &arg.r#type {
+            ArgType::Object(interface) | ArgType::NewId(interface) => {
+                let interface = interface_ref(interface, namespace);
+                types.push(quote! { Some(#interface) })
+            }
+            // Sent as the interface name, the version and the id.
+            ArgType::UnspecifiedNewId => {
+                types.extend([quote! { None }, quote! { None }, quote! { None }])
+            }
+            _ => types.push(quote! { None }),
+        }
+    }
+
+    quote! {
+        ::wayland_server_core::interface::Message {
+            name: #name,
+            signature: #signature,
+            types: &[ #( #types ),* ],
+        }
+    }
+}
+
+// Destructors take the resource by value. The server still has to destroy the object
+// once the event is sent.
+fn event_sender(event: &Event, name: &str, opcode: u16, namespace: &Namespace) -> TokenStream {
+    let name = value_ident(name);
+    let receiver = match event.r#type {
+        EventType::Default => quote! { &self },
+        EventType::Destructor => quote! { self },
+    };
+    let generics = if event
+        .args
+        .iter()
+        .any(|a| a.r#type == ArgType::UnspecifiedNewId)
+    {
+        quote! { <R: ::wayland_server_core::resource::Resource> }
+    } else {
+        quote! {}
+    };
+    let names = arg_names(&event.args);
+    let params = event
+        .args
+        .iter()
+        .zip(&names)
+        .map(|(arg, name)| event_parameter(arg, name, namespace));
+    let args = event_arguments(&event.args, &names);
+
+    quote! {
+        #[allow(clippy::too_many_arguments)]
+        pub fn #name #generics(
+            #receiver,
+            sink: &mut impl ::wayland_server_core::resource::Sink,
+            #( #params ),*
+        ) -> ::std::io::Result<()> {
+            sink.send_event(self.id, #opcode, &[ #( #args ),* ])
+        }
+    }
+}
+
+fn event_parameter(arg: &Arg, name: &str, namespace: &Namespace) -> TokenStream {
+    let ident = value_ident(name);
+    let type_name = match &arg.r#type {
+        ArgType::Int | ArgType::IntEnum(_) => quote! { i32 },
+        ArgType::UInt | ArgType::Enum(_) => quote! { u32 },
+        ArgType::Fixed => quote! { ::wayland_server_core::fixed::Fixed },
+        ArgType::String => quote! { &str },
+        ArgType::Object(interface) | ArgType::NewId(interface) => {
+            let path = namespace
+                .resolve(interface)
+                .expect("references are checked when the namespace is built");
+            quote! { &#path }
+        }
+        ArgType::UnspecifiedObject => quote! { u32 },
+        // Along with the version of the new resource.
+        ArgType::UnspecifiedNewId => quote! { (&R, u32) },
+        ArgType::Array => quote! { &[u8] },
+        ArgType::Fd => quote! { ::std::os::fd::BorrowedFd<'_> },
+    };
+
+    if arg.nullable {
+        quote! { #ident: Option<#type_name> }
+    } else {
+        quote! { #ident: #type_name }
+    }
+}
+
+fn event_arguments(args: &[Arg], names: &[String]) -> Vec<TokenStream> {
+    let mut arguments = Vec::<TokenStream>::new();
+    for (arg, name) in args.iter().zip(names) {
+        let ident = value_ident(name);
+        let argument = match (&arg.r#type, arg.nullable) {
+            (ArgType::Int | ArgType::IntEnum(_), _) => quote! { Int(#ident) },
+            (ArgType::UInt | ArgType::Enum(_), _) => quote! { Uint(#ident) },
+            (ArgType::Fixed, _) => quote! { Fixed(#ident) },
+            (ArgType::String, false) => quote! { String(Some(#ident)) },
+            (ArgType::String, true) => quote! { String(#ident) },
+            (ArgType::Object(_), false) => quote! {
+                Object(Some(::wayland_server_core::resource::Resource::get_id(#ident)))
+            },
+            (ArgType::Object(_), true) => quote! {
+                Object(#ident.map(::wayland_server_core::resource::Resource::get_id))
+            },
+            (ArgType::UnspecifiedObject, false) => quote! { Object(Some(#ident)) },
+            (ArgType::UnspecifiedObject, true) => quote! { Object(#ident) },
+            (ArgType::NewId(_), _) => quote! {
+                NewId(::wayland_server_core::resource::Resource::get_id(#ident))
+            },
+            (ArgType::UnspecifiedNewId, _) => {
+                arguments.push(quote! {
+                    ::wayland_server_core::wire::Argument::String(Some(R::INTERFACE.name))
+                });
+                arguments.push(quote! { ::wayland_server_core::wire::Argument::Uint(#ident.1) });
+                quote! { NewId(::wayland_server_core::resource::Resource::get_id(#ident.0)) }
+            }
+            (ArgType::Array, _) => quote! { Array(#ident) },
+            (ArgType::Fd, _) => quote! { Fd(#ident) },
+        };
+        arguments.push(quote! { ::wayland_server_core::wire::Argument::#argument });
+    }
+    arguments
+}
+
+impl GenServerTokens for (&Request, &str) {
+    fn to_tokens(self, namespace: &Namespace) -> TokenStream {
+        let (request, name) = self;
+        let name = Ident::new(name, Span::call_site());
+        let names = arg_names(&request.args);
+        let fields = request
+            .args
+            .iter()
+            .zip(&names)
+            .map(|(arg, name)| request_field(arg, name, namespace))
+            .collect::<Vec<TokenStream>>();
+        let mut patterns = Vec::<TokenStream>::new();
+        let mut values = Vec::<TokenStream>::new();
+        for (index, (arg, name)) in request.args.iter().zip(&names).enumerate() {
+            let ident = value_ident(name);
+            let (pattern, value) = request_value(arg, &format!("arg{index}"), namespace);
+            patterns.extend(pattern);
+            values.push(quote! { #ident: #value });
+        }
+        let count = patterns.len();
+
+        quote! {
+            pub struct #name {
+                #( #fields ),*
+            }
+
+            impl #name {
+                // From the arguments of a request with this opcode, `None` if they do not
+                // match its signature.
+                pub fn from_args(args: Vec<::wayland_server_core::wire::Value>) -> Option<Self> {
+                    let Ok([ #( #patterns ),* ]) =
+                        <[::wayland_server_core::wire::Value; #count]>::try_from(args)
+                    else {
+                        return None;
+                    };
+                    Some(Self { #( #values ),* })
+                }
+            }
+        }
+    }
+}
+
+fn request_field(arg: &Arg, name: &str, namespace: &Namespace) -> TokenStream {
+    let ident = value_ident(name);
+    let type_name = match &arg.r#type {
+        ArgType::Int | ArgType::IntEnum(_) => quote! { i32 },
+        ArgType::UInt | ArgType::Enum(_) => quote! { u32 },
+        ArgType::Fixed => quote! { ::wayland_server_core::fixed::Fixed },
+        ArgType::String => quote! { String },
+        ArgType::Object(interface) | ArgType::NewId(interface) => namespace
+            .resolve(interface)
+            .expect("references are checked when the namespace is built"),
+        ArgType::UnspecifiedObject => quote! { u32 },
+        // The interface name, the version and the id.
+        ArgType::UnspecifiedNewId => quote! { (String, u32, u32) },
+        ArgType::Array => quote! { Vec<u8> },
+        ArgType::Fd => quote! { ::std::os::fd::OwnedFd },
+    };
+
+    if arg.nullable {
+        quote! { pub #ident: Option<#type_name> }
+    } else {
+        quote! { pub #ident: #type_name }
+    }
+}
+
+// The patterns matching the values of an argument, and the field built from them.
+fn request_value(
+    arg: &Arg,
+    binding: &str,
+    namespace: &Namespace,
+) -> (Vec<TokenStream>, TokenStream) {
+    let ident = Ident::new(binding, Span::call_site());
+    let value = |pattern: TokenStream| quote! { ::wayland_server_core::wire::Value::#pattern };
+    let resource = |interface: &str| {
+        let path = namespace
+            .resolve(interface)
+            .expect("references are checked when the namespace is built");
+        quote! { <#path as ::wayland_server_core::resource::Resource>::from_id }
+    };
+    match (&arg.r#type, arg.nullable) {
+        (ArgType::Int | ArgType::IntEnum(_), _) => {
+            (vec![value(quote! { Int(#ident) })], quote! { #ident })
+        }
+        (ArgType::UInt | ArgType::Enum(_), _) => {
+            (vec![value(quote! { Uint(#ident) })], quote! { #ident })
+        }
+        (ArgType::Fixed, _) => (vec![value(quote! { Fixed(#ident) })], quote! { #ident }),
+        (ArgType::String, false) => (
+            vec![value(quote! { String(Some(#ident)) })],
+            quote! { #ident },
+        ),
+        (ArgType::String, true) => (vec![value(quote! { String(#ident) })], quote! { #ident }),
+        (ArgType::Object(interface), false) => {
+            let from_id = resource(interface);
+            (
+                vec![value(quote! { Object(Some(#ident)) })],
+                quote! { #from_id(#ident) },
+            )
+        }
+        (ArgType::Object(interface), true) => {
+            let from_id = resource(interface);
+            (
+                vec![value(quote! { Object(#ident) })],
+                quote! { #ident.map(#from_id) },
+            )
+        }
+        (ArgType::UnspecifiedObject, false) => (
+            vec![value(quote! { Object(Some(#ident)) })],
+            quote! { #ident },
+        ),
+        (ArgType::UnspecifiedObject, true) => {
+            (vec![value(quote! { Object(#ident) })], quote! { #ident })
+        }
+        (ArgType::NewId(interface), false) => {
+            let from_id = resource(interface);
+            (
+                vec![value(quote! { NewId(#ident) })],
+                quote! { #from_id(#ident) },
+            )
+        }
+        (ArgType::NewId(interface), true) => {
+            let from_id = resource(interface);
+            (
+                vec![value(quote! { NewId(#ident) })],
+                quote! { Some(#from_id(#ident)) },
+            )
+        }
+        (ArgType::UnspecifiedNewId, _) => {
+            let interface = Ident::new(&format!("{binding}_interface"), Span::call_site());
+            let version = Ident::new(&format!("{binding}_version"), Span::call_site());
+            let patterns = vec![
+                value(quote! { String(Some(#interface)) }),
+                value(quote! { Uint(#version) }),
+                value(quote! { NewId(#ident) }),
+            ];
+            let field = if arg.nullable {
+                quote! { Some((#interface, #version, #ident)) }
+            } else {
+                quote! { (#interface, #version, #ident) }
+            };
+            (patterns, field)
+        }
+        (ArgType::Array, _) => (vec![value(quote! { Array(#ident) })], quote! { #ident }),
+        (ArgType::Fd, _) => (vec![value(quote! { Fd(#ident) })], quote! { #ident }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{External, generate_server_code, parse_protocol_str};
+
+    #[test]
+    fn interfaces() {
+        let protocol = parse_protocol_str(
+            r#"<protocol name="test">
+                <interface name="test_toplevel" version="2">
+                    <request name="set_parent">
+                        <arg name="parent" type="object" interface="test_toplevel" allow-null="true"/>
+                        <arg name="surface" type="object" interface="wl_surface"/>
+                    </request>
+                    <event name="configure" since="2">
+                        <arg name="width" type="int"/>
+                        <arg name="title" type="string"/>
+                    </event>
+                </interface>
+            </protocol>"#,
+        )
+        .unwrap();
+        let externals =
+            [External::new("wl_surface", "wayland_server_protocol::wl::Surface").unwrap()];
+        let code = generate_server_code(&[protocol], &externals)
+            .unwrap()
+            .to_string();
+
+        // The interface refers to itself through its static, to others through their
+        // resource types.
+        assert!(code.contains(
+            "types : & [Some (& super :: test_toplevel :: INTERFACE) , Some (< wayland_server_protocol :: wl :: Surface as :: wayland_server_core :: resource :: Resource > :: INTERFACE)]"
+        ));
+        assert!(code.contains("signature : \"2is\""));
+        assert!(code.contains(
+            "sink . send_event (self . id , 0u16 , & [:: wayland_server_core :: wire :: Argument :: Int (width) , :: wayland_server_core :: wire :: Argument :: String (Some (title))])"
+        ));
+        assert!(code.contains("pub parent : Option < super :: TestToplevel >"));
+    }
+}
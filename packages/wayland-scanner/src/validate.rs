@@ -0,0 +1,368 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::{Arg, ArgType, Enum, Interface, Protocol};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+pub struct Issue {
+    severity: Severity,
+    location: String,
+    message: String,
+}
+
+impl Issue {
+    pub fn get_severity(&self) -> Severity {
+        self.severity
+    }
+
+    pub fn get_location(&self) -> &str {
+        &self.location
+    }
+
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.severity, self.location, self.message)
+    }
+}
+
+// Interfaces referenced by name but not found among `protocols` are only warned about,
+// as they may well be defined by a protocol that was not passed in.
+pub fn validate(protocols: &[Protocol]) -> Vec<Issue> {
+    let mut validator = Validator {
+        interfaces: HashMap::new(),
+        issues: Vec::new(),
+    };
+
+    for protocol in protocols {
+        for interface in &protocol.interfaces {
+            if validator
+                .interfaces
+                .insert(&interface.name, interface)
+                .is_some()
+            {
+                validator.push(
+                    Severity::Error,
+                    format!("{}::{}", protocol.name, interface.name),
+                    "interface is defined more than once".to_string(),
+                );
+            }
+        }
+    }
+
+    for protocol in protocols {
+        for interface in &protocol.interfaces {
+            validator.interface(&protocol.name, interface);
+        }
+    }
+
+    validator.issues
+}
+
+struct Validator<'a> {
+    interfaces: HashMap<&'a str, &'a Interface>,
+    issues: Vec<Issue>,
+}
+
+impl Validator<'_> {
+    fn push(&mut self, severity: Severity, location: String, message: String) {
+        self.issues.push(Issue {
+            severity,
+            location,
+            message,
+        });
+    }
+
+    fn interface(&mut self, protocol: &str, interface: &Interface) {
+        let location = format!("{protocol}::{}", interface.name);
+        self.name(&location, &interface.name);
+        if interface.max_version == 0 {
+            self.push(
+                Severity::Error,
+                location.clone(),
+                "interface version must be at least 1".to_string(),
+            );
+        }
+
+        self.duplicates(
+            &location,
+            "request",
+            interface.requests.iter().map(|r| r.name.as_str()),
+        );
+        self.duplicates(
+            &location,
+            "event",
+            interface.events.iter().map(|e| e.name.as_str()),
+        );
+        self.duplicates(
+            &location,
+            "enum",
+            interface.enums.iter().map(|e| e.name.as_str()),
+        );
+
+        for request in &interface.requests {
+            let location = format!("{location}.{}", request.name);
+            self.name(&location, &request.name);
+            self.versions(
+                &location,
+                interface.max_version,
+                request.since,
+                request.deprecated_since,
+            );
+            self.args(&location, interface, &request.args);
+        }
+
+        for event in &interface.events {
+            let location = format!("{location}.{}", event.name);
+            self.name(&location, &event.name);
+            self.versions(
+                &location,
+                interface.max_version,
+                event.since,
+                event.deprecated_since,
+            );
+            self.args(&location, interface, &event.args);
+            if event
+                .args
+                .iter()
+                .any(|a| matches!(a.r#type, ArgType::UnspecifiedNewId))
+            {
+                self.push(
+                    Severity::Error,
+                    location.clone(),
+                    "events cannot create objects of an unspecified interface".to_string(),
+                );
+            }
+        }
+
+        for en in &interface.enums {
+            self.r#enum(&location, interface.max_version, en);
+        }
+    }
+
+    fn r#enum(&mut self, location: &str, max_version: u32, en: &Enum) {
+        let location = format!("{location}.{}", en.name);
+        self.name(&location, &en.name);
+        self.versions(&location, max_version, en.since, None);
+        self.duplicates(
+            &location,
+            "entry",
+            en.entries.iter().map(|e| e.name.as_str()),
+        );
+
+        for entry in &en.entries {
+            let location = format!("{location}.{}", entry.name);
+            self.versions(&location, max_version, entry.since, entry.deprecated_since);
+        }
+    }
+
+    fn args(&mut self, location: &str, interface: &Interface, args: &[Arg]) {
+        self.duplicates(location, "argument", args.iter().map(|a| a.name.as_str()));
+
+        for arg in args {
+            let location = format!("{location}.{}", arg.name);
+            self.name(&location, &arg.name);
+
+            match &arg.r#type {
+                ArgType::Object(name) | ArgType::NewId(name) => {
+                    if !self.interfaces.contains_key(name.as_str()) {
+                        self.push(
+                            Severity::Warning,
+                            location.clone(),
+                            format!("refers to interface `{name}`, which is not defined here"),
+                        );
+                    }
+                }
+                ArgType::Enum(name) | ArgType::IntEnum(name) => {
+                    self.enum_reference(&location, interface, name)
+                }
+                _ => {}
+            }
+
+            if arg.nullable
+                && !matches!(
+                    arg.r#type,
+                    ArgType::String
+                        | ArgType::Object(_)
+                        | ArgType::UnspecifiedObject
+                        | ArgType::NewId(_)
+                        | ArgType::UnspecifiedNewId
+                        | ArgType::Array
+                )
+            {
+                self.push(
+                    Severity::Error,
+                    location,
+                    "only strings, arrays and objects can be nullable".to_string(),
+                );
+            }
+        }
+    }
+
+    fn enum_reference(&mut self, location: &str, interface: &Interface, name: &str) {
+        let (owner, en) = match name.split_once('.') {
+            Some((owner, en)) => match self.interfaces.get(owner) {
+                Some(owner) => (*owner, en),
+                None => {
+                    self.push(
+                        Severity::Warning,
+                        location.to_string(),
+                        format!("refers to enum `{name}` of an interface not defined here"),
+                    );
+                    return;
+                }
+            },
+            None => (interface, name),
+        };
+
+        if !owner.enums.iter().any(|e| e.name == en) {
+            self.push(
+                Severity::Error,
+                location.to_string(),
+                format!("refers to enum `{name}`, which does not exist"),
+            );
+        }
+    }
+
+    fn versions(
+        &mut self,
+        location: &str,
+        max_version: u32,
+        since: u32,
+        deprecated_since: Option<u32>,
+    ) {
+        if since == 0 || since > max_version {
+            self.push(
+                Severity::Error,
+                location.to_string(),
+                format!("since {since} is outside the interface versions 1..={max_version}"),
+            );
+        }
+        if let Some(deprecated_since) = deprecated_since
+            && (deprecated_since <= since || deprecated_since > max_version)
+        {
+            self.push(
+                Severity::Error,
+                location.to_string(),
+                format!(
+                    "deprecated-since {deprecated_since} must be after since {since} and at most {max_version}"
+                ),
+            );
+        }
+    }
+
+    fn duplicates<'b>(&mut self, location: &str, kind: &str, names: impl Iterator<Item = &'b str>) {
+        let mut seen = HashSet::<&str>::new();
+        for name in names {
+            if !seen.insert(name) {
+                self.push(
+                    Severity::Error,
+                    location.to_string(),
+                    format!("{kind} `{name}` is defined more than once"),
+                );
+            }
+        }
+    }
+
+    fn name(&mut self, location: &str, name: &str) {
+        let valid = name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+            && !name.starts_with(|c: char| c.is_ascii_digit());
+        if !valid {
+            self.push(
+                Severity::Warning,
+                location.to_string(),
+                format!("`{name}` is not a lower_snake_case name"),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Severity, validate};
+    use crate::{Protocol, parse_protocol_str};
+
+    fn parse(xml: &str) -> Protocol {
+        parse_protocol_str(xml).unwrap()
+    }
+
+    #[test]
+    fn valid() {
+        let protocol = parse(
+            r#"<protocol name="test">
+                <interface name="test_manager" version="2">
+                    <request name="get_thing" since="2">
+                        <arg name="id" type="new_id" interface="test_thing"/>
+                        <arg name="mode" type="uint" enum="mode"/>
+                    </request>
+                    <enum name="mode">
+                        <entry name="on" value="1"/>
+                    </enum>
+                </interface>
+                <interface name="test_thing" version="1"/>
+            </protocol>"#,
+        );
+
+        assert!(validate(&[protocol]).is_empty());
+    }
+
+    #[test]
+    fn since_after_version() {
+        let protocol = parse(
+            r#"<protocol name="test">
+                <interface name="test_manager" version="1">
+                    <event name="done" since="2"/>
+                </interface>
+            </protocol>"#,
+        );
+        let issues = validate(&[protocol]);
+
+        assert_eq!(1, issues.len());
+        assert_eq!(Severity::Error, issues[0].get_severity());
+        assert_eq!("test::test_manager.done", issues[0].get_location());
+    }
+
+    #[test]
+    fn unknown_references() {
+        let protocol = parse(
+            r#"<protocol name="test">
+                <interface name="test_manager" version="1">
+                    <request name="get_thing">
+                        <arg name="surface" type="object" interface="wl_surface"/>
+                        <arg name="mode" type="uint" enum="mode"/>
+                        <arg name="count" type="int" allow-null="true"/>
+                    </request>
+                </interface>
+            </protocol>"#,
+        );
+        let severities = validate(&[protocol])
+            .iter()
+            .map(|i| i.get_severity())
+            .collect::<Vec<Severity>>();
+
+        assert_eq!(
+            vec![Severity::Warning, Severity::Error, Severity::Error],
+            severities
+        );
+    }
+}
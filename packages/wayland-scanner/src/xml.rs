@@ -0,0 +1,327 @@
+use std::fmt::Write;
+
+use crate::{
+    Arg, ArgType, Description, Entry, Enum, Event, EventType, Interface, Protocol, Request,
+    RequestType,
+};
+
+const INDENT: &str = "  ";
+
+impl Protocol {
+    // Writes the protocol back out in a normalized form: requests, events and enums are
+    // grouped in that order, default attributes are omitted and bitfield values are hex.
+    pub fn to_xml(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+        writeln!(out, r#"<protocol name="{}">"#, escape(&self.name)).unwrap();
+
+        if let Some(copyright) = &self.copyright {
+            writeln!(out, "{INDENT}<copyright>").unwrap();
+            write_text(&mut out, 2, &copyright.0);
+            writeln!(out, "{INDENT}</copyright>").unwrap();
+        }
+        if let Some(description) = &self.description {
+            write_description(&mut out, 1, description);
+        }
+        for interface in &self.interfaces {
+            out.push('\n');
+            write_interface(&mut out, interface);
+        }
+
+        writeln!(out, "</protocol>").unwrap();
+        out
+    }
+}
+
+fn write_interface(out: &mut String, interface: &Interface) {
+    writeln!(
+        out,
+        r#"{INDENT}<interface name="{}" version="{}">"#,
+        escape(&interface.name),
+        interface.max_version
+    )
+    .unwrap();
+
+    if let Some(description) = &interface.description {
+        write_description(out, 2, description);
+    }
+    for request in &interface.requests {
+        out.push('\n');
+        write_request(out, request);
+    }
+    for event in &interface.events {
+        out.push('\n');
+        write_event(out, event);
+    }
+    for en in &interface.enums {
+        out.push('\n');
+        write_enum(out, en);
+    }
+
+    writeln!(out, "{INDENT}</interface>").unwrap();
+}
+
+fn write_request(out: &mut String, request: &Request) {
+    let r#type = match request.r#type {
+        RequestType::Default => None,
+        RequestType::Destructor => Some("destructor"),
+    };
+    write_message(
+        out,
+        "request",
+        &request.name,
+        r#type,
+        request.since,
+        request.deprecated_since,
+        request.description.as_ref(),
+        &request.args,
+    );
+}
+
+fn write_event(out: &mut String, event: &Event) {
+    let r#type = match event.r#type {
+        EventType::Default => None,
+        EventType::Destructor => Some("destructor"),
+    };
+    write_message(
+        out,
+        "event",
+        &event.name,
+        r#type,
+        event.since,
+        event.deprecated_since,
+        event.description.as_ref(),
+        &event.args,
+    );
+}
+
+#[allow(clippy::too_many_arguments)]
+fn write_message(
+    out: &mut String,
+    tag: &str,
+    name: &str,
+    r#type: Option<&str>,
+    since: u32,
+    deprecated_since: Option<u32>,
+    description: Option<&Description>,
+    args: &[Arg],
+) {
+    write!(out, r#"{INDENT}{INDENT}<{tag} name="{}""#, escape(name)).unwrap();
+    if let Some(r#type) = r#type {
+        write!(out, r#" type="{type}""#).unwrap();
+    }
+    write_versions(out, since, deprecated_since);
+
+    if description.is_none() && args.is_empty() {
+        writeln!(out, "/>").unwrap();
+        return;
+    }
+    writeln!(out, ">").unwrap();
+
+    if let Some(description) = description {
+        write_description(out, 3, description);
+    }
+    for arg in args {
+        write_arg(out, arg);
+    }
+
+    writeln!(out, "{INDENT}{INDENT}</{tag}>").unwrap();
+}
+
+fn write_arg(out: &mut String, arg: &Arg) {
+    let (r#type, interface, en) = match &arg.r#type {
+        ArgType::Int => ("int", None, None),
+        ArgType::UInt => ("uint", None, None),
+        ArgType::IntEnum(en) => ("int", None, Some(en)),
+        ArgType::Enum(en) => ("uint", None, Some(en)),
+        ArgType::Fixed => ("fixed", None, None),
+        ArgType::String => ("string", None, None),
+        ArgType::Object(interface) => ("object", Some(interface), None),
+        ArgType::UnspecifiedObject => ("object", None, None),
+        ArgType::NewId(interface) => ("new_id", Some(interface), None),
+        ArgType::UnspecifiedNewId => ("new_id", None, None),
+        ArgType::Array => ("array", None, None),
+        ArgType::Fd => ("fd", None, None),
+    };
+
+    write!(
+        out,
+        r#"{INDENT}{INDENT}{INDENT}<arg name="{}" type="{type}""#,
+        escape(&arg.name)
+    )
+    .unwrap();
+    if let Some(interface) = interface {
+        write!(out, r#" interface="{}""#, escape(interface)).unwrap();
+    }
+    if let Some(en) = en {
+        write!(out, r#" enum="{}""#, escape(en)).unwrap();
+    }
+    if arg.nullable {
+        write!(out, r#" allow-null="true""#).unwrap();
+    }
+    if let Some(summary) = &arg.summary {
+        write!(out, r#" summary="{}""#, escape(summary)).unwrap();
+    }
+
+    match &arg.description {
+        Some(description) => {
+            writeln!(out, ">").unwrap();
+            write_description(out, 4, description);
+            writeln!(out, "{INDENT}{INDENT}{INDENT}</arg>").unwrap();
+        }
+        None => writeln!(out, "/>").unwrap(),
+    }
+}
+
+fn write_enum(out: &mut String, en: &Enum) {
+    write!(out, r#"{INDENT}{INDENT}<enum name="{}""#, escape(&en.name)).unwrap();
+    if en.since != 1 {
+        write!(out, r#" since="{}""#, en.since).unwrap();
+    }
+    if en.is_bitfield {
+        write!(out, r#" bitfield="true""#).unwrap();
+    }
+    writeln!(out, ">").unwrap();
+
+    if let Some(description) = &en.description {
+        write_description(out, 3, description);
+    }
+    for entry in &en.entries {
+        write_entry(out, entry, en.is_bitfield);
+    }
+
+    writeln!(out, "{INDENT}{INDENT}</enum>").unwrap();
+}
+
+fn write_entry(out: &mut String, entry: &Entry, is_bitfield: bool) {
+    write!(
+        out,
+        r#"{INDENT}{INDENT}{INDENT}<entry name="{}""#,
+        escape(&entry.name)
+    )
+    .unwrap();
+    if is_bitfield {
+        write!(out, r#" value="{:#x}""#, entry.value).unwrap();
+    } else {
+        write!(out, r#" value="{}""#, entry.value).unwrap();
+    }
+    if let Some(summary) = &entry.summary {
+        write!(out, r#" summary="{}""#, escape(summary)).unwrap();
+    }
+    write_versions(out, entry.since, entry.deprecated_since);
+
+    match &entry.description {
+        Some(description) => {
+            writeln!(out, ">").unwrap();
+            write_description(out, 4, description);
+            writeln!(out, "{INDENT}{INDENT}{INDENT}</entry>").unwrap();
+        }
+        None => writeln!(out, "/>").unwrap(),
+    }
+}
+
+fn write_versions(out: &mut String, since: u32, deprecated_since: Option<u32>) {
+    if since != 1 {
+        write!(out, r#" since="{since}""#).unwrap();
+    }
+    if let Some(deprecated_since) = deprecated_since {
+        write!(out, r#" deprecated-since="{deprecated_since}""#).unwrap();
+    }
+}
+
+fn write_description(out: &mut String, depth: usize, description: &Description) {
+    let indent = INDENT.repeat(depth);
+    write!(
+        out,
+        r#"{indent}<description summary="{}""#,
+        escape(&description.summary)
+    )
+    .unwrap();
+
+    match &description.content {
+        Some(content) if !content.is_empty() => {
+            writeln!(out, ">").unwrap();
+            write_text(out, depth + 1, content);
+            writeln!(out, "{indent}</description>").unwrap();
+        }
+        _ => writeln!(out, "/>").unwrap(),
+    }
+}
+
+fn write_text(out: &mut String, depth: usize, text: &str) {
+    let indent = INDENT.repeat(depth);
+    for line in text.lines() {
+        if line.is_empty() {
+            out.push('\n');
+        } else {
+            writeln!(out, "{indent}{}", escape(line)).unwrap();
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse_protocol_str;
+
+    const PROTOCOL: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<protocol name="test">
+  <copyright>
+    Copyright &amp; more
+  </copyright>
+
+  <interface name="test_manager" version="3">
+    <description summary="a manager">
+      Creates things,
+      lots of them.
+
+        Indented.
+    </description>
+
+    <request name="destroy" type="destructor"/>
+
+    <request name="get_thing" since="2">
+      <arg name="id" type="new_id" interface="test_thing"/>
+      <arg name="surface" type="object" interface="wl_surface" allow-null="true"/>
+      <arg name="transform" type="int" enum="wl_output.transform" summary="a &quot;transform&quot;"/>
+    </request>
+
+    <event name="done" deprecated-since="3"/>
+
+    <enum name="flags" since="2" bitfield="true">
+      <entry name="none" value="0x0"/>
+      <entry name="one" value="0x1" summary="one"/>
+    </enum>
+  </interface>
+</protocol>
+"#;
+
+    #[test]
+    fn round_trip() {
+        let protocol = parse_protocol_str(PROTOCOL).unwrap();
+
+        assert_eq!(PROTOCOL, protocol.to_xml());
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let protocol = parse_protocol_str(PROTOCOL).unwrap();
+        let json = serde_json::to_string(&protocol).unwrap();
+        let parsed = serde_json::from_str::<crate::Protocol>(&json).unwrap();
+
+        assert_eq!(PROTOCOL, parsed.to_xml());
+    }
+}
@@ -0,0 +1,41 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+// Found the same way as by the build scripts, which need it as well.
+fn wayland_xml() -> PathBuf {
+    let dirs = std::env::var("XDG_DATA_DIRS").unwrap_or("/usr/local/share:/usr/share".into());
+    dirs.split(':')
+        .map(|dir| PathBuf::from(dir).join("wayland/wayland.xml"))
+        .find(|path| path.is_file())
+        .expect("wayland.xml is installed")
+}
+
+fn scanner(command: &str) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_wayland-scanner"))
+        .arg(command)
+        .arg(wayland_xml())
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let code = String::from_utf8(output.stdout).unwrap();
+    syn::parse_file(&code).expect("the output is valid Rust");
+    code
+}
+
+#[test]
+fn client() {
+    let code = scanner("client");
+    assert!(code.contains("pub mod display {"));
+    assert!(code.contains("pub struct Registry"));
+}
+
+#[test]
+fn server() {
+    let code = scanner("server");
+    assert!(code.contains("pub mod display {"));
+    assert!(code.contains("impl ::wayland_server_core::resource::Resource for Registry"));
+}
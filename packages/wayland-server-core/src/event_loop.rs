@@ -0,0 +1,462 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+use std::rc::Rc;
+use std::time::Duration;
+
+// How many events one `epoll_wait` returns at most, like libwayland.
+const MAX_EVENTS: usize = 32;
+
+type FdCallback<D> = Box<dyn FnMut(&mut D, &EventLoop<D>, Readiness)>;
+type Callback<D> = Box<dyn FnMut(&mut D, &EventLoop<D>)>;
+type IdleCallback<D> = Box<dyn FnOnce(&mut D, &EventLoop<D>)>;
+
+// The equivalent of `wl_event_loop`. Callbacks get the loop, so that they can add and
+// remove sources, themselves included. Clones are handles to the same loop.
+pub struct EventLoop<D> {
+    inner: Rc<Inner<D>>,
+}
+
+struct Inner<D> {
+    epoll: OwnedFd,
+    sources: RefCell<HashMap<u64, Source<D>>>,
+    next_id: Cell<u64>,
+    idles: RefCell<VecDeque<IdleCallback<D>>>,
+    before_sleep: RefCell<Vec<Callback<D>>>,
+}
+
+struct Source<D> {
+    fd: OwnedFd,
+    // Taken out while it runs.
+    callback: Option<SourceCallback<D>>,
+}
+
+enum SourceCallback<D> {
+    Fd(FdCallback<D>),
+    Timer(Callback<D>),
+    Signal(Callback<D>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SourceId(u64);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Interest {
+    pub readable: bool,
+    pub writable: bool,
+}
+
+impl Interest {
+    pub const READABLE: Self = Self {
+        readable: true,
+        writable: false,
+    };
+
+    pub const WRITABLE: Self = Self {
+        readable: false,
+        writable: true,
+    };
+
+    pub const BOTH: Self = Self {
+        readable: true,
+        writable: true,
+    };
+
+    fn to_epoll(self) -> u32 {
+        let mut events = 0;
+        if self.readable {
+            events |= libc::EPOLLIN as u32;
+        }
+        if self.writable {
+            events |= libc::EPOLLOUT as u32;
+        }
+        events
+    }
+}
+
+// Hang-ups and errors are reported whatever the interest.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Readiness {
+    pub readable: bool,
+    pub writable: bool,
+    pub hangup: bool,
+    pub error: bool,
+}
+
+impl Readiness {
+    fn from_epoll(events: u32) -> Self {
+        Self {
+            readable: events & libc::EPOLLIN as u32 != 0,
+            writable: events & libc::EPOLLOUT as u32 != 0,
+            hangup: events & libc::EPOLLHUP as u32 != 0,
+            error: events & libc::EPOLLERR as u32 != 0,
+        }
+    }
+}
+
+impl<D> Clone for EventLoop<D> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<D> EventLoop<D> {
+    pub fn new() -> io::Result<Self> {
+        let epoll = cvt(unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) })?;
+        Ok(Self {
+            inner: Rc::new(Inner {
+                epoll: unsafe { OwnedFd::from_raw_fd(epoll) },
+                sources: RefCell::new(HashMap::new()),
+                next_id: Cell::new(0),
+                idles: RefCell::new(VecDeque::new()),
+                before_sleep: RefCell::new(Vec::new()),
+            }),
+        })
+    }
+
+    // The fd is duplicated, like `wl_event_loop_add_fd` does. The source has to be
+    // removed for the loop to let go of it.
+    pub fn add_fd(
+        &self,
+        fd: BorrowedFd<'_>,
+        interest: Interest,
+        callback: impl FnMut(&mut D, &EventLoop<D>, Readiness) + 'static,
+    ) -> io::Result<SourceId> {
+        self.insert(
+            fd.try_clone_to_owned()?,
+            interest,
+            SourceCallback::Fd(Box::new(callback)),
+        )
+    }
+
+    pub fn update_fd(&self, id: SourceId, interest: Interest) -> io::Result<()> {
+        let sources = self.inner.sources.borrow();
+        let source = sources.get(&id.0).ok_or(io::ErrorKind::NotFound)?;
+        self.ctl(libc::EPOLL_CTL_MOD, source.fd.as_fd(), interest, id)
+    }
+
+    // The timer starts disarmed, see `set_timer`.
+    pub fn add_timer(
+        &self,
+        callback: impl FnMut(&mut D, &EventLoop<D>) + 'static,
+    ) -> io::Result<SourceId> {
+        let fd = cvt(unsafe {
+            libc::timerfd_create(
+                libc::CLOCK_MONOTONIC,
+                libc::TFD_CLOEXEC | libc::TFD_NONBLOCK,
+            )
+        })?;
+        self.insert(
+            unsafe { OwnedFd::from_raw_fd(fd) },
+            Interest::READABLE,
+            SourceCallback::Timer(Box::new(callback)),
+        )
+    }
+
+    // Fires the timer once, after `delay`. A zero delay disarms it.
+    pub fn set_timer(&self, id: SourceId, delay: Duration) -> io::Result<()> {
+        let sources = self.inner.sources.borrow();
+        let source = sources.get(&id.0).ok_or(io::ErrorKind::NotFound)?;
+        let spec = libc::itimerspec {
+            it_interval: libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            },
+            it_value: libc::timespec {
+                tv_sec: delay.as_secs() as libc::time_t,
+                tv_nsec: delay.subsec_nanos() as libc::c_long,
+            },
+        };
+        cvt(unsafe {
+            libc::timerfd_settime(source.fd.as_raw_fd(), 0, &spec, std::ptr::null_mut())
+        })?;
+        Ok(())
+    }
+
+    // The signal is blocked in the calling thread, so that it is only delivered
+    // through the loop. Like libwayland, it is left blocked when the source is removed.
+    pub fn add_signal(
+        &self,
+        signal: i32,
+        callback: impl FnMut(&mut D, &EventLoop<D>) + 'static,
+    ) -> io::Result<SourceId> {
+        let fd = unsafe {
+            let mut mask = std::mem::zeroed::<libc::sigset_t>();
+            libc::sigemptyset(&mut mask);
+            cvt(libc::sigaddset(&mut mask, signal))?;
+            let error = libc::pthread_sigmask(libc::SIG_BLOCK, &mask, std::ptr::null_mut());
+            if error != 0 {
+                return Err(io::Error::from_raw_os_error(error));
+            }
+            cvt(libc::signalfd(
+                -1,
+                &mask,
+                libc::SFD_CLOEXEC | libc::SFD_NONBLOCK,
+            ))?
+        };
+        self.insert(
+            unsafe { OwnedFd::from_raw_fd(fd) },
+            Interest::READABLE,
+            SourceCallback::Signal(Box::new(callback)),
+        )
+    }
+
+    // Runs once, before the loop waits for the next events.
+    pub fn add_idle(&self, callback: impl FnOnce(&mut D, &EventLoop<D>) + 'static) {
+        self.inner.idles.borrow_mut().push_back(Box::new(callback));
+    }
+
+    // Runs every time the loop is about to wait, after the idle callbacks. This is
+    // where the server flushes its clients.
+    pub fn add_before_sleep(&self, callback: impl FnMut(&mut D, &EventLoop<D>) + 'static) {
+        self.inner
+            .before_sleep
+            .borrow_mut()
+            .push(Box::new(callback));
+    }
+
+    // Closes the fd of the source. Removing a source from its own callback is fine.
+    pub fn remove(&self, id: SourceId) {
+        let source = self.inner.sources.borrow_mut().remove(&id.0);
+        if let Some(source) = source {
+            // Closing the fd would be enough, if nobody else had it open.
+            let _ = cvt(unsafe {
+                libc::epoll_ctl(
+                    self.inner.epoll.as_raw_fd(),
+                    libc::EPOLL_CTL_DEL,
+                    source.fd.as_raw_fd(),
+                    std::ptr::null_mut(),
+                )
+            });
+        }
+    }
+
+    // Runs the idle callbacks, waits at most `timeout` for events, forever if `None`,
+    // and runs the callbacks of the ready sources.
+    pub fn dispatch(&self, state: &mut D, timeout: Option<Duration>) -> io::Result<()> {
+        self.dispatch_idle(state);
+        self.before_sleep(state);
+
+        // Rounded up, a timeout below a millisecond would not sleep at all.
+        let timeout = match timeout {
+            Some(timeout) => timeout
+                .as_nanos()
+                .div_ceil(1_000_000)
+                .try_into()
+                .unwrap_or(i32::MAX),
+            None => -1,
+        };
+        let mut events = [libc::epoll_event { events: 0, u64: 0 }; MAX_EVENTS];
+        let count = match cvt(unsafe {
+            libc::epoll_wait(
+                self.inner.epoll.as_raw_fd(),
+                events.as_mut_ptr(),
+                MAX_EVENTS as i32,
+                timeout,
+            )
+        }) {
+            Ok(count) => count as usize,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => 0,
+            Err(e) => return Err(e),
+        };
+
+        for event in &events[..count] {
+            self.dispatch_source(state, event.u64, Readiness::from_epoll(event.events));
+        }
+
+        self.dispatch_idle(state);
+        Ok(())
+    }
+
+    fn insert(
+        &self,
+        fd: OwnedFd,
+        interest: Interest,
+        callback: SourceCallback<D>,
+    ) -> io::Result<SourceId> {
+        let id = SourceId(self.inner.next_id.get());
+        self.ctl(libc::EPOLL_CTL_ADD, fd.as_fd(), interest, id)?;
+        self.inner.next_id.set(id.0 + 1);
+        self.inner.sources.borrow_mut().insert(
+            id.0,
+            Source {
+                fd,
+                callback: Some(callback),
+            },
+        );
+        Ok(id)
+    }
+
+    fn ctl(&self, op: i32, fd: BorrowedFd<'_>, interest: Interest, id: SourceId) -> io::Result<()> {
+        let mut event = libc::epoll_event {
+            events: interest.to_epoll(),
+            u64: id.0,
+        };
+        cvt(unsafe {
+            libc::epoll_ctl(self.inner.epoll.as_raw_fd(), op, fd.as_raw_fd(), &mut event)
+        })?;
+        Ok(())
+    }
+
+    fn dispatch_source(&self, state: &mut D, id: u64, readiness: Readiness) {
+        // Removed by a callback that ran before, in the same dispatch.
+        let Some((fd, mut callback)) = self
+            .inner
+            .sources
+            .borrow_mut()
+            .get_mut(&id)
+            .and_then(|source| Some((source.fd.as_raw_fd(), source.callback.take()?)))
+        else {
+            return;
+        };
+
+        match &mut callback {
+            SourceCallback::Fd(callback) => callback(state, self, readiness),
+            SourceCallback::Timer(callback) => {
+                let mut expirations = 0u64;
+                if read_struct(fd, &mut expirations) {
+                    callback(state, self);
+                }
+            }
+            SourceCallback::Signal(callback) => {
+                let mut info = unsafe { std::mem::zeroed::<libc::signalfd_siginfo>() };
+                while read_struct(fd, &mut info) {
+                    callback(state, self);
+                }
+            }
+        }
+
+        if let Some(source) = self.inner.sources.borrow_mut().get_mut(&id) {
+            source.callback.get_or_insert(callback);
+        }
+    }
+
+    fn dispatch_idle(&self, state: &mut D) {
+        loop {
+            let Some(idle) = self.inner.idles.borrow_mut().pop_front() else {
+                return;
+            };
+            idle(state, self);
+        }
+    }
+
+    fn before_sleep(&self, state: &mut D) {
+        let mut callbacks = std::mem::take(&mut *self.inner.before_sleep.borrow_mut());
+        for callback in &mut callbacks {
+            callback(state, self);
+        }
+        // Keeping those added while the others ran.
+        let mut before_sleep = self.inner.before_sleep.borrow_mut();
+        callbacks.append(&mut before_sleep);
+        *before_sleep = callbacks;
+    }
+}
+
+// `false` if there was nothing to read, the fd being non-blocking.
+fn read_struct<T>(fd: i32, value: &mut T) -> bool {
+    let size = size_of::<T>();
+    let read = unsafe { libc::read(fd, (value as *mut T).cast(), size) };
+    read == size as isize
+}
+
+fn cvt(result: i32) -> io::Result<i32> {
+    if result == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::os::fd::AsFd;
+    use std::os::unix::net::UnixStream;
+    use std::time::{Duration, Instant};
+
+    use super::{EventLoop, Interest};
+
+    #[test]
+    fn fds() {
+        let event_loop = EventLoop::<Vec<usize>>::new().unwrap();
+        let (mut a, b) = UnixStream::pair().unwrap();
+        let id = event_loop
+            .add_fd(b.as_fd(), Interest::READABLE, |state, _, readiness| {
+                assert!(readiness.readable);
+                state.push(1);
+            })
+            .unwrap();
+
+        let mut state = Vec::new();
+        event_loop
+            .dispatch(&mut state, Some(Duration::ZERO))
+            .unwrap();
+        assert!(state.is_empty());
+
+        a.write_all(b"x").unwrap();
+        event_loop
+            .dispatch(&mut state, Some(Duration::ZERO))
+            .unwrap();
+        assert_eq!(vec![1], state);
+
+        event_loop.remove(id);
+        event_loop
+            .dispatch(&mut state, Some(Duration::ZERO))
+            .unwrap();
+        assert_eq!(vec![1], state);
+    }
+
+    #[test]
+    fn timers_and_idles() {
+        let event_loop = EventLoop::<Vec<&str>>::new().unwrap();
+        let timer = event_loop
+            .add_timer(|state, event_loop| {
+                state.push("timer");
+                event_loop.add_idle(|state, _| state.push("idle"));
+            })
+            .unwrap();
+        event_loop.add_before_sleep(|state, _| state.push("sleep"));
+        event_loop
+            .set_timer(timer, Duration::from_millis(1))
+            .unwrap();
+
+        let mut state = Vec::new();
+        event_loop.dispatch(&mut state, None).unwrap();
+        assert_eq!(vec!["sleep", "timer", "idle"], state);
+
+        // One-shot.
+        event_loop
+            .dispatch(&mut state, Some(Duration::from_millis(5)))
+            .unwrap();
+        assert_eq!(vec!["sleep", "timer", "idle", "sleep"], state);
+    }
+
+    #[test]
+    fn short_timeout() {
+        let event_loop = EventLoop::<()>::new().unwrap();
+        let start = Instant::now();
+        event_loop
+            .dispatch(&mut (), Some(Duration::from_micros(100)))
+            .unwrap();
+        assert!(start.elapsed() >= Duration::from_micros(100));
+    }
+
+    #[test]
+    fn signals() {
+        let event_loop = EventLoop::<u32>::new().unwrap();
+        event_loop
+            .add_signal(libc::SIGUSR2, |state, _| *state += 1)
+            .unwrap();
+        // Blocked, so pending on this thread until the loop reads it.
+        assert_eq!(0, unsafe { libc::raise(libc::SIGUSR2) });
+
+        let mut state = 0;
+        event_loop
+            .dispatch(&mut state, Some(Duration::ZERO))
+            .unwrap();
+        assert_eq!(1, state);
+    }
+}
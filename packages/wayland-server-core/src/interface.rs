@@ -0,0 +1,29 @@
+// A description of an interface, as needed to check and decode the requests sent to its
+// objects. Like libwayland's `wl_interface`.
+pub struct Interface {
+    pub name: &'static str,
+    pub version: u32,
+    // Indexed by opcode.
+    pub requests: &'static [Message],
+    pub events: &'static [Message],
+}
+
+pub struct Message {
+    pub name: &'static str,
+    // The libwayland signature, e.g. `2uo?s`.
+    pub signature: &'static str,
+    // The interface of each argument that is an object or a new id, if the protocol
+    // names one. Like libwayland's `types`.
+    pub types: &'static [Option<&'static Interface>],
+}
+
+impl Message {
+    // The version the message appeared in.
+    pub fn get_since(&self) -> u32 {
+        let digits = self
+            .signature
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(self.signature.len());
+        self.signature[..digits].parse().unwrap_or(1)
+    }
+}
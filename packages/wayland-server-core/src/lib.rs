@@ -1,14 +1,7 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
-}
+pub mod event_loop;
+pub mod interface;
+pub mod object_map;
+pub mod resource;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
-    }
-}
+// Messages are laid out the same both ways, so the server uses the client's encoding.
+pub use wayland_client_core::{fixed, wire};
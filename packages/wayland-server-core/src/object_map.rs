@@ -0,0 +1,209 @@
+use crate::interface::Interface;
+
+// Ids from here on are allocated by the server, for objects it creates.
+pub const SERVER_ID_START: u32 = 0xFF000000;
+
+pub struct Object {
+    interface: &'static Interface,
+    version: u32,
+    destructor: Option<Box<dyn FnOnce()>>,
+}
+
+impl Object {
+    fn new(interface: &'static Interface, version: u32) -> Self {
+        Self {
+            interface,
+            version,
+            destructor: None,
+        }
+    }
+
+    pub fn get_interface(&self) -> &'static Interface {
+        self.interface
+    }
+
+    pub fn get_version(&self) -> u32 {
+        self.version
+    }
+}
+
+// The objects of a single client. Ids start at 1, which is always `wl_display`. The
+// destructors of the objects left run when the map is dropped, i.e. when the client
+// disconnects.
+#[derive(Default)]
+pub struct ObjectMap {
+    objects: Vec<Option<Object>>,
+    server_objects: Vec<Option<Object>>,
+    // The objects in both, counted as they come and go so that `len` is cheap.
+    live: usize,
+}
+
+impl ObjectMap {
+    // For ids chosen by the client. Like libwayland, a client may reuse a free id or take
+    // the next one, but not skip ahead.
+    pub fn insert_at(
+        &mut self,
+        id: u32,
+        interface: &'static Interface,
+        version: u32,
+    ) -> Result<(), IdError> {
+        self.check_new_id(id)?;
+        let index = id as usize - 1;
+        if index == self.objects.len() {
+            self.objects.push(None);
+        }
+        self.objects[index] = Some(Object::new(interface, version));
+        self.live += 1;
+        Ok(())
+    }
+
+    // Whether the client may create an object with this id.
+    pub fn check_new_id(&self, id: u32) -> Result<(), IdError> {
+        if id == 0 || id >= SERVER_ID_START {
+            return Err(IdError::OutOfRange);
+        }
+        match self.objects.get(id as usize - 1) {
+            Some(Some(_)) => Err(IdError::InUse),
+            Some(None) => Ok(()),
+            None if id as usize - 1 == self.objects.len() => Ok(()),
+            None => Err(IdError::OutOfSequence),
+        }
+    }
+
+    // Takes the lowest free id from `SERVER_ID_START` on.
+    pub fn allocate(&mut self, interface: &'static Interface, version: u32) -> u32 {
+        let object = Some(Object::new(interface, version));
+        let index = match self.server_objects.iter().position(Option::is_none) {
+            Some(index) => {
+                self.server_objects[index] = object;
+                index
+            }
+            None => {
+                self.server_objects.push(object);
+                self.server_objects.len() - 1
+            }
+        };
+        self.live += 1;
+        SERVER_ID_START + index as u32
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Object> {
+        match id.checked_sub(SERVER_ID_START) {
+            Some(index) => self.server_objects.get(index as usize)?.as_ref(),
+            None => self.objects.get((id as usize).checked_sub(1)?)?.as_ref(),
+        }
+    }
+
+    // Replaces the previous destructor, if any.
+    pub fn set_destructor(&mut self, id: u32, destructor: impl FnOnce() + 'static) {
+        if let Some(Some(object)) = self.slot(id) {
+            object.destructor = Some(Box::new(destructor));
+        }
+    }
+
+    // Runs the destructor of the object and frees its id. Returns `false` if there was
+    // no such object.
+    pub fn destroy(&mut self, id: u32) -> bool {
+        let Some(mut object) = self.slot(id).and_then(Option::take) else {
+            return false;
+        };
+        self.live -= 1;
+        if let Some(destructor) = object.destructor.take() {
+            destructor();
+        }
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.live
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn slot(&mut self, id: u32) -> Option<&mut Option<Object>> {
+        match id.checked_sub(SERVER_ID_START) {
+            Some(index) => self.server_objects.get_mut(index as usize),
+            None => self.objects.get_mut((id as usize).checked_sub(1)?),
+        }
+    }
+}
+
+impl Drop for ObjectMap {
+    fn drop(&mut self) {
+        let objects = self.objects.iter_mut().chain(&mut self.server_objects);
+        for object in objects.flatten() {
+            if let Some(destructor) = object.destructor.take() {
+                destructor();
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum IdError {
+    // Zero, or in the range of server-created ids.
+    OutOfRange,
+    InUse,
+    // Past the next free id.
+    OutOfSequence,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::{IdError, ObjectMap, SERVER_ID_START};
+    use crate::interface::Interface;
+
+    static CALLBACK: Interface = Interface {
+        name: "wl_callback",
+        version: 1,
+        requests: &[],
+        events: &[],
+    };
+
+    #[test]
+    fn insert_at() {
+        let mut objects = ObjectMap::default();
+        assert_eq!(Ok(()), objects.insert_at(1, &CALLBACK, 1));
+        assert_eq!(Ok(()), objects.insert_at(2, &CALLBACK, 1));
+        assert_eq!(Err(IdError::InUse), objects.insert_at(2, &CALLBACK, 1));
+        assert_eq!(
+            Err(IdError::OutOfSequence),
+            objects.insert_at(4, &CALLBACK, 1)
+        );
+        assert_eq!(Err(IdError::OutOfRange), objects.insert_at(0, &CALLBACK, 1));
+        assert_eq!(
+            Err(IdError::OutOfRange),
+            objects.insert_at(SERVER_ID_START, &CALLBACK, 1)
+        );
+
+        objects.destroy(2);
+        assert_eq!(1, objects.len());
+        assert_eq!(Ok(()), objects.insert_at(2, &CALLBACK, 1));
+        assert_eq!(SERVER_ID_START, objects.allocate(&CALLBACK, 3));
+        assert_eq!(SERVER_ID_START + 1, objects.allocate(&CALLBACK, 3));
+        assert_eq!(4, objects.len());
+    }
+
+    #[test]
+    fn destructors() {
+        let destroyed = Rc::new(RefCell::new(Vec::new()));
+        let mut objects = ObjectMap::default();
+        for id in 1..=3 {
+            objects.insert_at(id, &CALLBACK, 1).unwrap();
+            let destroyed = destroyed.clone();
+            objects.set_destructor(id, move || destroyed.borrow_mut().push(id));
+        }
+
+        assert!(objects.destroy(2));
+        assert!(!objects.destroy(2));
+        assert_eq!(vec![2], *destroyed.borrow());
+
+        drop(objects);
+        assert_eq!(vec![2, 1, 3], *destroyed.borrow());
+    }
+}
@@ -0,0 +1,19 @@
+use std::io;
+
+use crate::interface::Interface;
+use crate::wire::Argument;
+
+// Implemented by generated code for every interface. A resource is only its id, the
+// objects themselves are kept by the server.
+pub trait Resource {
+    const INTERFACE: &'static Interface;
+
+    fn from_id(id: u32) -> Self;
+
+    fn get_id(&self) -> u32;
+}
+
+// Where the events of generated resources go, i.e. the client owning them.
+pub trait Sink {
+    fn send_event(&mut self, sender: u32, opcode: u16, args: &[Argument]) -> io::Result<()>;
+}
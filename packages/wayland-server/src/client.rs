@@ -0,0 +1,517 @@
+use std::collections::VecDeque;
+use std::io::{self, IoSlice, IoSliceMut};
+use std::net::Shutdown;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::{AncillaryData, SocketAncillary, UCred, UnixStream};
+
+use wayland_server_core::event_loop::SourceId;
+use wayland_server_core::interface::Interface;
+use wayland_server_core::object_map::{IdError, ObjectMap, SERVER_ID_START};
+use wayland_server_core::resource::Sink;
+use wayland_server_core::wire::{self, Argument, HEADER_SIZE, Value};
+
+use crate::protocol::{
+    DISPLAY, DISPLAY_DELETE_ID_OPCODE, DISPLAY_ERROR_IMPLEMENTATION, DISPLAY_ERROR_INVALID_METHOD,
+    DISPLAY_ERROR_INVALID_OBJECT, DISPLAY_ERROR_NO_MEMORY, DISPLAY_ERROR_OPCODE, DISPLAY_ID,
+};
+
+// The limits libwayland puts on a single `sendmsg`/`recvmsg`.
+const MAX_BUFFER_SIZE: usize = 4096;
+const MAX_FDS: usize = 28;
+// Fds received but not claimed by a request yet, as many as fit the 4 KiB buffer of
+// libwayland.
+const MAX_INCOMING_FDS: usize = 1024;
+
+// What a client may make the server hold for it, so that one client can't take the
+// memory of the compositor.
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    // The objects of the client, `wl_display` included. Only new ids sent by the client
+    // are refused past it.
+    pub max_objects: usize,
+    // Requests received but not handled yet. Nothing more is read from the socket
+    // until there is room, and a larger request is a protocol error.
+    pub max_incoming_size: usize,
+    // Fds a single request may carry. A request with more is a protocol error.
+    pub max_message_fds: usize,
+    // Events not sent yet, because the client does not read them.
+    pub max_outgoing_size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_objects: 1 << 16,
+            // The default buffer size of libwayland, which bounds single requests
+            // too. The wire format allows up to 64 KiB.
+            max_incoming_size: MAX_BUFFER_SIZE,
+            // As many as libwayland sends with one message.
+            max_message_fds: MAX_FDS,
+            max_outgoing_size: 1 << 20,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ClientId(u64);
+
+impl ClientId {
+    pub(crate) fn new(id: u64) -> Self {
+        Self(id)
+    }
+}
+
+// A connected client. Everything it owns, its objects included, goes away with it.
+pub struct Client {
+    id: ClientId,
+    stream: UnixStream,
+    // Of the process that connected, as the kernel saw it then.
+    credentials: UCred,
+    pidfd: Option<OwnedFd>,
+    pub(crate) incoming: Buffer,
+    pub(crate) outgoing: Buffer,
+    pub(crate) objects: ObjectMap,
+    // The `wl_registry` objects of the client, which are told about globals.
+    pub(crate) registries: Vec<u32>,
+    // Set once a protocol error was sent, or the client fell too far behind reading its
+    // events. Nothing else is sent after it.
+    errored: bool,
+    limits: Limits,
+    // Set when the server polls the client in an event loop.
+    pub(crate) source: Option<SourceId>,
+    // The socket was full the last time the client was flushed.
+    pub(crate) blocked: bool,
+}
+
+// A request that was checked against the interface of its object.
+pub struct Request {
+    pub sender: u32,
+    pub interface: &'static Interface,
+    pub opcode: u16,
+    pub args: Vec<Value>,
+}
+
+// A `wl_display.error` sent to a client.
+#[derive(Debug)]
+pub struct ProtocolError {
+    pub object: u32,
+    pub code: u32,
+    pub message: String,
+}
+
+#[derive(Default)]
+pub(crate) struct Buffer {
+    pub(crate) bytes: Vec<u8>,
+    pub(crate) fds: VecDeque<OwnedFd>,
+}
+
+impl Client {
+    pub(crate) fn new(id: ClientId, stream: UnixStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        let credentials = stream.peer_cred()?;
+        let pidfd = peer_pidfd(&stream);
+        let mut objects = ObjectMap::default();
+        objects
+            .insert_at(DISPLAY_ID, &DISPLAY, 1)
+            .expect("the object map is empty");
+        Ok(Self {
+            id,
+            stream,
+            credentials,
+            pidfd,
+            incoming: Buffer::default(),
+            outgoing: Buffer::default(),
+            objects,
+            registries: Vec::new(),
+            errored: false,
+            limits: Limits::default(),
+            source: None,
+            blocked: false,
+        })
+    }
+
+    pub fn get_id(&self) -> ClientId {
+        self.id
+    }
+
+    pub fn get_pid(&self) -> i32 {
+        self.credentials.pid.unwrap_or(0)
+    }
+
+    pub fn get_uid(&self) -> u32 {
+        self.credentials.uid
+    }
+
+    pub fn get_gid(&self) -> u32 {
+        self.credentials.gid
+    }
+
+    // Unlike the pid, it keeps referring to the same process after it exited. `None` on
+    // kernels before 6.5, or if the process was already gone when it connected.
+    pub fn get_pidfd(&self) -> Option<BorrowedFd<'_>> {
+        self.pidfd.as_ref().map(|fd| fd.as_fd())
+    }
+
+    pub fn get_objects(&self) -> &ObjectMap {
+        &self.objects
+    }
+
+    pub fn get_limits(&self) -> Limits {
+        self.limits
+    }
+
+    // E.g. to trust a client more than the others. Lowering a limit does not affect
+    // what the client already holds.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    // For a `new_id` sent by the client.
+    pub fn insert_resource(
+        &mut self,
+        id: u32,
+        interface: &'static Interface,
+        version: u32,
+    ) -> Result<(), IdError> {
+        self.objects.insert_at(id, interface, version)
+    }
+
+    // For a `new_id` sent by the server, e.g. in `wl_data_device.data_offer`.
+    pub fn create_resource(&mut self, interface: &'static Interface, version: u32) -> u32 {
+        self.objects.allocate(interface, version)
+    }
+
+    // Runs when the resource is destroyed, or when the client disconnects.
+    pub fn set_destructor(&mut self, id: u32, destructor: impl FnOnce() + 'static) {
+        self.objects.set_destructor(id, destructor);
+    }
+
+    // Ids chosen by the client are only free for it to reuse after `wl_display.delete_id`.
+    pub fn destroy_resource(&mut self, id: u32) {
+        if self.objects.destroy(id) && id < SERVER_ID_START {
+            self.send_event(DISPLAY_ID, DISPLAY_DELETE_ID_OPCODE, &[Argument::Uint(id)])
+                .expect("no fds to duplicate");
+        }
+    }
+
+    // Sends `wl_display.error` about `object` and disconnects. Nothing is sent to the
+    // client afterwards, and the server drops it once it notices the hang-up.
+    pub fn post_error(&mut self, object: u32, code: u32, message: &str) {
+        if self.errored {
+            return;
+        }
+        self.send_event(
+            DISPLAY_ID,
+            DISPLAY_ERROR_OPCODE,
+            &[
+                Argument::Object(Some(object)),
+                Argument::Uint(code),
+                Argument::String(Some(message)),
+            ],
+        )
+        .expect("no fds to duplicate");
+        self.errored = true;
+        let _ = self.flush();
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+
+    pub fn post_no_memory(&mut self) {
+        self.post_error(DISPLAY_ID, DISPLAY_ERROR_NO_MEMORY, "no memory");
+    }
+
+    // For a bug in the server, rather than in the client.
+    pub fn post_implementation_error(&mut self, message: &str) {
+        self.post_error(DISPLAY_ID, DISPLAY_ERROR_IMPLEMENTATION, message);
+    }
+
+    pub fn is_errored(&self) -> bool {
+        self.errored
+    }
+
+    // Queued until the next flush. A client that lets the queue grow past its limit is
+    // disconnected, like libwayland does once its buffer is full.
+    pub(crate) fn send_event(
+        &mut self,
+        sender: u32,
+        opcode: u16,
+        args: &[Argument],
+    ) -> io::Result<()> {
+        if self.errored {
+            return Ok(());
+        }
+        let mut fds = Vec::new();
+        wire::encode(sender, opcode, args, &mut self.outgoing.bytes, &mut fds)?;
+        self.outgoing.fds.extend(fds);
+
+        if self.outgoing.bytes.len() > self.limits.max_outgoing_size {
+            let _ = self.flush();
+        }
+        if self.outgoing.bytes.len() > self.limits.max_outgoing_size {
+            // The server notices the hang-up, no need to make every caller check.
+            self.errored = true;
+            self.outgoing = Buffer::default();
+            let _ = self.stream.shutdown(Shutdown::Both);
+        }
+        Ok(())
+    }
+
+    // The next complete request. One that does not fit the interface of its object, in
+    // its arguments, or in the ids it refers to, is answered with a protocol error the
+    // way libwayland does.
+    pub(crate) fn next_request(&mut self) -> Result<Option<Request>, ProtocolError> {
+        if self.errored {
+            return Ok(None);
+        }
+        let Some((header, _)) = self.incoming.bytes.split_first_chunk::<HEADER_SIZE>() else {
+            return Ok(None);
+        };
+        let sender = u32::from_ne_bytes(header[..4].try_into().unwrap());
+        let word = u32::from_ne_bytes(header[4..].try_into().unwrap());
+        let (size, opcode) = ((word >> 16) as usize, word as u16);
+        if size < HEADER_SIZE || size % 4 != 0 {
+            return Err(self.fail(
+                DISPLAY_ID,
+                DISPLAY_ERROR_INVALID_METHOD,
+                format!("invalid message size {size}"),
+            ));
+        }
+        if size > self.limits.max_incoming_size {
+            return Err(self.fail(
+                DISPLAY_ID,
+                DISPLAY_ERROR_NO_MEMORY,
+                format!("message size {size} over the limit"),
+            ));
+        }
+        if self.incoming.bytes.len() < size {
+            return Ok(None);
+        }
+
+        let Some(object) = self.objects.get(sender) else {
+            return Err(self.fail(
+                DISPLAY_ID,
+                DISPLAY_ERROR_INVALID_OBJECT,
+                format!("invalid object {sender}"),
+            ));
+        };
+        let (interface, version) = (object.get_interface(), object.get_version());
+        let Some(message) = interface.requests.get(opcode as usize) else {
+            return Err(self.fail(
+                sender,
+                DISPLAY_ERROR_INVALID_METHOD,
+                format!(
+                    "invalid method {opcode}, object {}@{sender}",
+                    interface.name
+                ),
+            ));
+        };
+        if message.get_since() > version {
+            return Err(self.fail(
+                sender,
+                DISPLAY_ERROR_INVALID_METHOD,
+                format!(
+                    "invalid method {opcode} (since {} > {version}), object {}@{sender}",
+                    message.get_since(),
+                    interface.name
+                ),
+            ));
+        }
+
+        let fd_count = message.signature.matches('h').count();
+        if fd_count > self.limits.max_message_fds {
+            return Err(self.fail(
+                DISPLAY_ID,
+                DISPLAY_ERROR_NO_MEMORY,
+                format!(
+                    "too many fds ({fd_count}), message {}@{sender}.{}",
+                    interface.name, message.name
+                ),
+            ));
+        }
+
+        let Buffer { bytes, fds } = &mut self.incoming;
+        let args = wire::decode(message.signature, &bytes[HEADER_SIZE..size], fds);
+        bytes.drain(..size);
+        let Some(args) = args else {
+            return Err(self.fail(
+                sender,
+                DISPLAY_ERROR_INVALID_METHOD,
+                format!(
+                    "invalid arguments for {}@{sender}.{}",
+                    interface.name, message.name
+                ),
+            ));
+        };
+        for (index, arg) in args.iter().enumerate() {
+            let expected = message.types.get(index).copied().flatten();
+            match arg {
+                Value::Object(Some(id)) => {
+                    let Some(object) = self.objects.get(*id) else {
+                        return Err(self.fail(
+                            DISPLAY_ID,
+                            DISPLAY_ERROR_INVALID_OBJECT,
+                            format!(
+                                "unknown object ({id}), message {}@{sender}.{}",
+                                interface.name, message.name
+                            ),
+                        ));
+                    };
+                    // By name, the same interface may be described in several crates.
+                    let actual = object.get_interface().name;
+                    if let Some(expected) = expected
+                        && expected.name != actual
+                    {
+                        return Err(self.fail(
+                            DISPLAY_ID,
+                            DISPLAY_ERROR_INVALID_OBJECT,
+                            format!(
+                                "invalid object {actual}@{id}, expected {}, message {}@{sender}.{}",
+                                expected.name, interface.name, message.name
+                            ),
+                        ));
+                    }
+                }
+                Value::NewId(id) if self.objects.check_new_id(*id).is_err() => {
+                    return Err(self.fail(
+                        DISPLAY_ID,
+                        DISPLAY_ERROR_INVALID_OBJECT,
+                        format!("invalid new id {id}"),
+                    ));
+                }
+                Value::NewId(_) if self.objects.len() >= self.limits.max_objects => {
+                    return Err(self.fail(
+                        DISPLAY_ID,
+                        DISPLAY_ERROR_NO_MEMORY,
+                        format!(
+                            "too many objects, message {}@{sender}.{}",
+                            interface.name, message.name
+                        ),
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Some(Request {
+            sender,
+            interface,
+            opcode,
+            args,
+        }))
+    }
+
+    fn fail(&mut self, object: u32, code: u32, message: String) -> ProtocolError {
+        self.post_error(object, code, &message);
+        ProtocolError {
+            object,
+            code,
+            message,
+        }
+    }
+
+    // Reads everything available without blocking, as far as the incoming buffer has
+    // room. Returns `false` once the client has hung up.
+    pub(crate) fn read(&mut self) -> io::Result<bool> {
+        let mut buffer = [0; MAX_BUFFER_SIZE];
+        let mut ancillary_buffer = [0; 256];
+        loop {
+            let room = self
+                .limits
+                .max_incoming_size
+                .saturating_sub(self.incoming.bytes.len())
+                .min(MAX_BUFFER_SIZE);
+            if room == 0 {
+                return Ok(true);
+            }
+            let mut ancillary = SocketAncillary::new(&mut ancillary_buffer);
+            let read = match self.stream.recv_vectored_with_ancillary(
+                &mut [IoSliceMut::new(&mut buffer[..room])],
+                &mut ancillary,
+            ) {
+                Ok(0) => return Ok(false),
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(true),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+
+            self.incoming.bytes.extend_from_slice(&buffer[..read]);
+            for message in ancillary.messages() {
+                if let Ok(AncillaryData::ScmRights(rights)) = message {
+                    self.incoming
+                        .fds
+                        .extend(rights.map(|fd| unsafe { OwnedFd::from_raw_fd(fd) }));
+                }
+            }
+            // The kernel closed the fds that did not fit.
+            if ancillary.truncated() || self.incoming.fds.len() > MAX_INCOMING_FDS {
+                self.post_no_memory();
+                return Err(io::Error::other("too many fds"));
+            }
+        }
+    }
+
+    // Sends as much as the socket takes. What is left is sent by the next flush, once
+    // the fd is writable again.
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        while !self.outgoing.bytes.is_empty() {
+            let fds = self
+                .outgoing
+                .fds
+                .iter()
+                .take(MAX_FDS)
+                .map(|fd| fd.as_raw_fd())
+                .collect::<Vec<RawFd>>();
+            let mut ancillary_buffer = [0; 256];
+            let mut ancillary = SocketAncillary::new(&mut ancillary_buffer);
+            ancillary.add_fds(&fds);
+
+            let end = self.outgoing.bytes.len().min(MAX_BUFFER_SIZE);
+            let slice = [IoSlice::new(&self.outgoing.bytes[..end])];
+            match self
+                .stream
+                .send_vectored_with_ancillary(&slice, &mut ancillary)
+            {
+                Ok(written) => {
+                    self.outgoing.bytes.drain(..written);
+                    self.outgoing.fds.drain(..fds.len());
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+// Any failure leaves the client without a pidfd: `ENOPROTOOPT` before Linux 6.5, but
+// also `ESRCH` or `EINVAL` when the peer already exited or was reaped.
+fn peer_pidfd(stream: &UnixStream) -> Option<OwnedFd> {
+    let mut fd: libc::c_int = -1;
+    let mut len = size_of::<libc::c_int>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERPIDFD,
+            (&raw mut fd).cast(),
+            &mut len,
+        )
+    };
+    (result == 0).then(|| unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+// For polling: readable when the client sent something, writable when a flush that
+// did not get everything out can continue.
+impl AsFd for Client {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.stream.as_fd()
+    }
+}
+
+// For the event methods of generated resources.
+impl Sink for Client {
+    fn send_event(&mut self, sender: u32, opcode: u16, args: &[Argument]) -> io::Result<()> {
+        Client::send_event(self, sender, opcode, args)
+    }
+}
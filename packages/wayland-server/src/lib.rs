@@ -1 +1,7 @@
+#![feature(peer_credentials_unix_socket, unix_socket_ancillary_data)]
+pub mod client;
+pub mod protocol;
 pub mod server;
+mod socket;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
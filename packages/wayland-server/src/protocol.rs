@@ -0,0 +1,82 @@
+use wayland_server_core::interface::{Interface, Message};
+
+// The interfaces of the core protocol the server implements itself, from `wayland.xml`.
+
+pub(crate) const DISPLAY_ID: u32 = 1;
+
+pub(crate) const DISPLAY_SYNC_OPCODE: u16 = 0;
+pub(crate) const DISPLAY_GET_REGISTRY_OPCODE: u16 = 1;
+pub(crate) const DISPLAY_ERROR_OPCODE: u16 = 0;
+pub(crate) const DISPLAY_DELETE_ID_OPCODE: u16 = 1;
+
+pub(crate) const DISPLAY_ERROR_INVALID_OBJECT: u32 = 0;
+pub(crate) const DISPLAY_ERROR_INVALID_METHOD: u32 = 1;
+pub(crate) const DISPLAY_ERROR_NO_MEMORY: u32 = 2;
+pub(crate) const DISPLAY_ERROR_IMPLEMENTATION: u32 = 3;
+
+pub(crate) const CALLBACK_DONE_OPCODE: u16 = 0;
+
+pub(crate) const REGISTRY_GLOBAL_OPCODE: u16 = 0;
+pub(crate) const REGISTRY_GLOBAL_REMOVE_OPCODE: u16 = 1;
+
+pub static DISPLAY: Interface = Interface {
+    name: "wl_display",
+    version: 1,
+    requests: &[
+        Message {
+            name: "sync",
+            signature: "n",
+            types: &[Some(&CALLBACK)],
+        },
+        Message {
+            name: "get_registry",
+            signature: "n",
+            types: &[Some(&REGISTRY)],
+        },
+    ],
+    events: &[
+        Message {
+            name: "error",
+            signature: "ous",
+            types: &[None, None, None],
+        },
+        Message {
+            name: "delete_id",
+            signature: "u",
+            types: &[None],
+        },
+    ],
+};
+
+pub static REGISTRY: Interface = Interface {
+    name: "wl_registry",
+    version: 1,
+    requests: &[Message {
+        name: "bind",
+        signature: "usun",
+        types: &[None, None, None, None],
+    }],
+    events: &[
+        Message {
+            name: "global",
+            signature: "usu",
+            types: &[None, None, None],
+        },
+        Message {
+            name: "global_remove",
+            signature: "u",
+            types: &[None],
+        },
+    ],
+};
+
+pub static CALLBACK: Interface = Interface {
+    name: "wl_callback",
+    version: 1,
+    requests: &[],
+    events: &[Message {
+        name: "done",
+        signature: "u",
+        types: &[None],
+    }],
+};
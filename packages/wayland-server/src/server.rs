@@ -1,13 +1,1140 @@
-use std::{io::Result, os::unix::net::UnixListener};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd};
+use std::os::unix::net::UnixStream;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::ptr;
 
+use wayland_server_core::event_loop::{EventLoop, Interest, Readiness, SourceId};
+use wayland_server_core::interface::Interface;
+use wayland_server_core::wire::{Argument, Value};
+
+use crate::client::{Client, ClientId, Limits, ProtocolError, Request};
+use crate::protocol::{
+    CALLBACK, CALLBACK_DONE_OPCODE, DISPLAY, DISPLAY_ERROR_INVALID_OBJECT,
+    DISPLAY_GET_REGISTRY_OPCODE, DISPLAY_SYNC_OPCODE, REGISTRY, REGISTRY_GLOBAL_OPCODE,
+    REGISTRY_GLOBAL_REMOVE_OPCODE,
+};
+pub use crate::socket::BindError;
+use crate::socket::Socket;
+
+type GlobalFilter = Box<dyn Fn(&Client, &Global) -> bool>;
+type BindCallback = Box<dyn FnMut(&mut Client, u32, u32)>;
+
+#[derive(Default)]
 pub struct Server {
-    socket: UnixListener,
+    sockets: Vec<Socket>,
+    clients: HashMap<ClientId, Client>,
+    next_client_id: u64,
+    globals: Vec<Global>,
+    next_global_name: u32,
+    filter: Option<GlobalFilter>,
+    serial: u32,
+    limits: Limits,
+    // Set by `insert_into`.
+    poll: Option<Box<dyn Poll>>,
+    socket_sources: Vec<SourceId>,
+}
+
+// What the server needs of the event loop, without knowing the state type.
+trait Poll {
+    fn add_socket(&self, fd: BorrowedFd<'_>) -> io::Result<SourceId>;
+    fn add_client(&self, id: ClientId, fd: BorrowedFd<'_>) -> io::Result<SourceId>;
+    fn update(&self, source: SourceId, interest: Interest) -> io::Result<()>;
+    fn remove(&self, source: SourceId);
+}
+
+struct Registration<D> {
+    event_loop: EventLoop<D>,
+    get: fn(&mut D) -> &mut Server,
+    handler: fn(&mut D, ClientId, Request),
+}
+
+pub struct Global {
+    name: u32,
+    interface: &'static Interface,
+    version: u32,
+    bind: BindCallback,
+    // Set by `remove_global`, until `destroy_global`.
+    removed: bool,
+}
+
+impl Global {
+    // The number clients bind it by.
+    pub fn get_name(&self) -> u32 {
+        self.name
+    }
+
+    pub fn get_interface(&self) -> &'static Interface {
+        self.interface
+    }
+
+    pub fn get_version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn is_removed(&self) -> bool {
+        self.removed
+    }
 }
 
 impl Server {
-    pub fn new() -> Result<Self> {
-        Ok(Self {
-            socket: UnixListener::bind("/run/user/1000/wayland-0")?,
-        })
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Listens on the first free `wayland-<n>` in `XDG_RUNTIME_DIR`, like
+    // `wl_display_add_socket_auto`. Returns the name, to be exported as `WAYLAND_DISPLAY`
+    // to clients.
+    pub fn add_socket_auto(&mut self) -> Result<String, BindError> {
+        let socket = Socket::auto(&runtime_dir()?)?;
+        let name = socket
+            .get_name()
+            .expect("sockets bound by the server have a name")
+            .to_string();
+        self.push_socket(socket)?;
+        Ok(name)
+    }
+
+    // Listens on `name` in `XDG_RUNTIME_DIR`, or on `name` itself if it is an absolute
+    // path. Fails with `BindError::InUse` if another server listens there.
+    pub fn add_socket(&mut self, name: &str) -> Result<(), BindError> {
+        let path = if Path::new(name).is_absolute() {
+            PathBuf::from(name)
+        } else {
+            runtime_dir()?.join(name)
+        };
+        self.push_socket(Socket::bind(path)?)
+    }
+
+    // Adopts a socket that is already bound and listening, e.g. one of systemd's
+    // `LISTEN_FDS` or one created by a sandbox. It is not removed when the server is
+    // dropped.
+    pub fn add_socket_fd(&mut self, fd: OwnedFd) -> Result<(), BindError> {
+        self.push_socket(Socket::from_fd(fd)?)
+    }
+
+    fn push_socket(&mut self, socket: Socket) -> Result<(), BindError> {
+        if let Some(poll) = &self.poll {
+            let source = poll.add_socket(socket.as_fd()).map_err(BindError::Io)?;
+            self.socket_sources.push(source);
+        }
+        self.sockets.push(socket);
+        Ok(())
+    }
+
+    // To be polled for readability, after which `accept` takes the new clients.
+    pub fn get_socket_fds(&self) -> Vec<BorrowedFd<'_>> {
+        self.sockets.iter().map(|s| s.as_fd()).collect()
+    }
+
+    // Takes every pending connection, without blocking. Like libwayland, a connection
+    // the client cannot be created for is closed, and a socket that fails to accept,
+    // e.g. for lack of fds, is retried the next time it is readable; neither affects
+    // the other connections.
+    pub fn accept(&mut self) -> Vec<ClientId> {
+        let mut clients = Vec::new();
+        for index in 0..self.sockets.len() {
+            while let Ok(Some(stream)) = self.sockets[index].accept() {
+                if let Ok(id) = self.create_client(stream) {
+                    clients.push(id);
+                }
+            }
+        }
+        clients
+    }
+
+    // Adds a client connected some other way than through the sockets, e.g. one end of
+    // a socketpair.
+    pub fn create_client(&mut self, stream: UnixStream) -> io::Result<ClientId> {
+        let id = ClientId::new(self.next_client_id);
+        self.next_client_id += 1;
+        let mut client = Client::new(id, stream)?;
+        client.set_limits(self.limits);
+        if let Some(poll) = &self.poll {
+            client.source = Some(poll.add_client(id, client.as_fd())?);
+        }
+        self.clients.insert(id, client);
+        Ok(id)
+    }
+
+    // A command for a client that is connected before it starts, through the fd named
+    // by `WAYLAND_SOCKET`. The fd is only inherited by the process spawned from the
+    // command, and is closed in the server when the command is dropped.
+    pub fn create_client_command(
+        &mut self,
+        program: impl AsRef<OsStr>,
+    ) -> io::Result<(ClientId, Command)> {
+        let (server_end, client_end) = UnixStream::pair()?;
+        let id = self.create_client(server_end)?;
+
+        let fd = OwnedFd::from(client_end);
+        let mut command = Command::new(program);
+        command.env("WAYLAND_SOCKET", fd.as_raw_fd().to_string());
+        // Both ends are created with CLOEXEC, the flag is cleared in the child only.
+        unsafe {
+            command.pre_exec(move || {
+                if libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        Ok((id, command))
+    }
+
+    // For the clients created from now on.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn get_client(&self, id: ClientId) -> Option<&Client> {
+        self.clients.get(&id)
+    }
+
+    pub fn get_client_mut(&mut self, id: ClientId) -> Option<&mut Client> {
+        self.clients.get_mut(&id)
+    }
+
+    pub fn get_clients(&self) -> impl Iterator<Item = &Client> {
+        self.clients.values()
+    }
+
+    // To be called when the client's fd is readable. A client that hung up or whose
+    // socket failed is disconnected, and the error returned.
+    pub fn read(&mut self, id: ClientId) -> io::Result<()> {
+        let client = self.clients.get_mut(&id).ok_or(io::ErrorKind::NotFound)?;
+        let result = match client.read() {
+            Ok(true) => return Ok(()),
+            Ok(false) => Err(io::ErrorKind::UnexpectedEof.into()),
+            Err(e) => Err(e),
+        };
+        self.disconnect(id);
+        result
+    }
+
+    // Sends what is buffered for the client, as far as it can without blocking. A
+    // client whose socket failed is disconnected, and the error returned.
+    pub fn flush(&mut self, id: ClientId) -> io::Result<()> {
+        let client = self.clients.get_mut(&id).ok_or(io::ErrorKind::NotFound)?;
+        let result = client.flush();
+        if result.is_err() {
+            self.disconnect(id);
+        }
+        result
+    }
+
+    // Advertises a global to every client that may see it, now and whenever it creates
+    // a `wl_registry`. `bind` is called with the client, the id of the new resource and
+    // the version it asked for, after the resource was added to the client.
+    //
+    // Panics if `version` is 0 or above the version of `interface`.
+    pub fn create_global(
+        &mut self,
+        interface: &'static Interface,
+        version: u32,
+        bind: impl FnMut(&mut Client, u32, u32) + 'static,
+    ) -> u32 {
+        assert!(
+            (1..=interface.version).contains(&version),
+            "`{}` has no version {version}",
+            interface.name
+        );
+        self.next_global_name += 1;
+        let global = Global {
+            name: self.next_global_name,
+            interface,
+            version,
+            bind: Box::new(bind),
+            removed: false,
+        };
+        for client in self.clients.values_mut() {
+            if is_visible(&self.filter, client, &global) {
+                send_global(client, &global);
+            }
+        }
+        self.globals.push(global);
+        self.next_global_name
+    }
+
+    // Stops advertising a global, like `wl_global_remove`. Clients may have bound it
+    // before they saw `wl_registry.global_remove`, so binding it keeps working, and
+    // calling its bind callback, until `destroy_global`. The resources it creates from
+    // then on should be made inert.
+    pub fn remove_global(&mut self, name: u32) {
+        let Some(global) = self
+            .globals
+            .iter_mut()
+            .find(|g| g.name == name && !g.removed)
+        else {
+            return;
+        };
+        global.removed = true;
+        for client in self.clients.values_mut() {
+            if is_visible(&self.filter, client, global) {
+                for registry in client.registries.clone() {
+                    client
+                        .send_event(
+                            registry,
+                            REGISTRY_GLOBAL_REMOVE_OPCODE,
+                            &[Argument::Uint(name)],
+                        )
+                        .expect("no fds to duplicate");
+                }
+            }
+        }
+    }
+
+    // Forgets a global, removing it first if it was not. Binding it fails from then on,
+    // so a compositor waits a few seconds after `remove_global`, as with libwayland.
+    pub fn destroy_global(&mut self, name: u32) {
+        self.remove_global(name);
+        self.globals.retain(|g| g.name != name);
+    }
+
+    // Decides which globals a client sees and may bind. Globals are only filtered when
+    // they are advertised, so the filter should give the same answer for a client and
+    // a global every time.
+    pub fn set_global_filter(&mut self, filter: impl Fn(&Client, &Global) -> bool + 'static) {
+        self.filter = Some(Box::new(filter));
+    }
+
+    // For events that need a unique, increasing number, e.g. `wl_callback.done` of
+    // `wl_display.sync`.
+    pub fn next_serial(&mut self) -> u32 {
+        self.serial = self.serial.wrapping_add(1);
+        self.serial
+    }
+
+    // Decodes the requests `read` got from the client. Those to `wl_display` and
+    // `wl_registry` are handled by the server, the others returned. A client that sent
+    // something invalid gets a protocol error and is disconnected.
+    //
+    // New ids are checked against the resources that exist when they are decoded, so
+    // the returned requests can't create objects the following ones use. Handle them
+    // with `next_request` then.
+    pub fn dispatch(&mut self, id: ClientId) -> Result<Vec<Request>, ProtocolError> {
+        let mut requests = Vec::new();
+        while let Some(request) = self.next_request(id)? {
+            requests.push(request);
+        }
+        Ok(requests)
+    }
+
+    // Like `dispatch`, one request at a time. `None` once the buffered requests are
+    // handled, or if the client is gone.
+    pub fn next_request(&mut self, id: ClientId) -> Result<Option<Request>, ProtocolError> {
+        loop {
+            let Some(client) = self.clients.get_mut(&id) else {
+                return Ok(None);
+            };
+            let request = match client.next_request() {
+                Ok(Some(request)) => request,
+                Ok(None) => return Ok(None),
+                Err(e) => {
+                    self.disconnect(id);
+                    return Err(e);
+                }
+            };
+
+            if ptr::eq(request.interface, &DISPLAY) {
+                self.handle_display(id, request);
+            } else if ptr::eq(request.interface, &REGISTRY) {
+                if let Err(e) = self.handle_registry(id, request) {
+                    self.post_error(id, e.object, e.code, &e.message);
+                    return Err(e);
+                }
+            } else {
+                return Ok(Some(request));
+            }
+        }
+    }
+
+    // The arguments were checked against the signatures, new ids included.
+    fn handle_display(&mut self, id: ClientId, request: Request) {
+        // Like libwayland, only the callbacks of `wl_display.sync` take a serial.
+        let serial = match request.opcode {
+            DISPLAY_SYNC_OPCODE => self.next_serial(),
+            _ => self.serial,
+        };
+        let client = self
+            .clients
+            .get_mut(&id)
+            .expect("the client sent the request");
+        match (request.opcode, request.args.as_slice()) {
+            (DISPLAY_SYNC_OPCODE, [Value::NewId(callback)]) => {
+                client
+                    .insert_resource(*callback, &CALLBACK, 1)
+                    .expect("new ids are checked when decoding");
+                client
+                    .send_event(*callback, CALLBACK_DONE_OPCODE, &[Argument::Uint(serial)])
+                    .expect("no fds to duplicate");
+                client.destroy_resource(*callback);
+            }
+            (DISPLAY_GET_REGISTRY_OPCODE, [Value::NewId(registry)]) => {
+                client
+                    .insert_resource(*registry, &REGISTRY, 1)
+                    .expect("new ids are checked when decoding");
+                client.registries.push(*registry);
+                for global in &self.globals {
+                    if !global.removed && is_visible(&self.filter, client, global) {
+                        send_global(client, global);
+                    }
+                }
+            }
+            _ => unreachable!("arguments do not match the signature"),
+        }
+    }
+
+    fn handle_registry(&mut self, id: ClientId, request: Request) -> Result<(), ProtocolError> {
+        let [
+            Value::Uint(name),
+            Value::String(Some(interface)),
+            Value::Uint(version),
+            Value::NewId(new_id),
+        ] = request.args.as_slice()
+        else {
+            unreachable!("arguments do not match the signature")
+        };
+        self.bind(id, *name, interface, *version, *new_id)
+            .map_err(|message| ProtocolError {
+                object: request.sender,
+                code: DISPLAY_ERROR_INVALID_OBJECT,
+                message,
+            })
+    }
+
+    // Checks the request against the global the client may see, with the messages of
+    // libwayland, then calls the bind callback.
+    fn bind(
+        &mut self,
+        client: ClientId,
+        name: u32,
+        interface: &str,
+        version: u32,
+        id: u32,
+    ) -> Result<(), String> {
+        let client = self
+            .clients
+            .get_mut(&client)
+            .expect("the client sent the request");
+        let global = self
+            .globals
+            .iter_mut()
+            .find(|g| g.name == name)
+            .filter(|g| is_visible(&self.filter, client, g))
+            .ok_or_else(|| format!("invalid global {interface} ({name})"))?;
+        if global.interface.name != interface {
+            return Err(format!(
+                "invalid interface for global {name}: have {interface}, wanted {}",
+                global.interface.name
+            ));
+        }
+        if version == 0 || version > global.version {
+            return Err(format!(
+                "invalid version for global {interface} ({name}): have {}, wanted {version}",
+                global.version
+            ));
+        }
+
+        client
+            .insert_resource(id, global.interface, version)
+            .expect("new ids are checked when decoding");
+        (global.bind)(client, id, version);
+        Ok(())
+    }
+
+    // Sends `wl_display.error` about `object` and disconnects the client.
+    pub fn post_error(&mut self, id: ClientId, object: u32, code: u32, message: &str) {
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.post_error(object, code, message);
+        }
+        self.disconnect(id);
+    }
+
+    // Runs the destructors of the client's resources.
+    pub fn disconnect(&mut self, id: ClientId) {
+        let Some(client) = self.clients.remove(&id) else {
+            return;
+        };
+        if let (Some(poll), Some(source)) = (&self.poll, client.source) {
+            poll.remove(source);
+        }
+    }
+
+    // Flushes every client, like `wl_display_flush_clients`. Those whose socket is full
+    // are polled for writability until they catch up.
+    pub fn flush_clients(&mut self) {
+        let ids = self.clients.keys().copied().collect::<Vec<ClientId>>();
+        for id in ids {
+            if self.flush(id).is_err() {
+                continue;
+            }
+            let client = self.clients.get_mut(&id).expect("flushed without error");
+            let blocked = !client.outgoing.bytes.is_empty();
+            if let (Some(poll), Some(source)) = (&self.poll, client.source)
+                && blocked != client.blocked
+            {
+                let interest = if blocked {
+                    Interest::BOTH
+                } else {
+                    Interest::READABLE
+                };
+                if poll.update(source, interest).is_err() {
+                    self.disconnect(id);
+                    continue;
+                }
+                client.blocked = blocked;
+            }
+        }
+    }
+
+    // Polls the sockets and the clients in `event_loop`, those added later included,
+    // and flushes the clients before the loop sleeps. The server is found in the state
+    // with `get`. Requests the server does not handle itself go to `handler`.
+    pub fn insert_into<D: 'static>(
+        &mut self,
+        event_loop: &EventLoop<D>,
+        get: fn(&mut D) -> &mut Server,
+        handler: fn(&mut D, ClientId, Request),
+    ) -> io::Result<()> {
+        let poll = Registration {
+            event_loop: event_loop.clone(),
+            get,
+            handler,
+        };
+        for socket in &self.sockets {
+            self.socket_sources.push(poll.add_socket(socket.as_fd())?);
+        }
+        for (id, client) in &mut self.clients {
+            client.source = Some(poll.add_client(*id, client.as_fd())?);
+        }
+        event_loop.add_before_sleep(move |state, _| get(state).flush_clients());
+        self.poll = Some(Box::new(poll));
+        Ok(())
+    }
+}
+
+// The loop holds duplicates of the fds, which would keep the clients connected.
+impl Drop for Server {
+    fn drop(&mut self) {
+        let Some(poll) = &self.poll else {
+            return;
+        };
+        for source in &self.socket_sources {
+            poll.remove(*source);
+        }
+        for client in self.clients.values() {
+            if let Some(source) = client.source {
+                poll.remove(source);
+            }
+        }
+    }
+}
+
+impl<D: 'static> Poll for Registration<D> {
+    fn add_socket(&self, fd: BorrowedFd<'_>) -> io::Result<SourceId> {
+        let get = self.get;
+        self.event_loop
+            .add_fd(fd, Interest::READABLE, move |state, _, _| {
+                get(state).accept();
+            })
+    }
+
+    fn add_client(&self, id: ClientId, fd: BorrowedFd<'_>) -> io::Result<SourceId> {
+        let (get, handler) = (self.get, self.handler);
+        self.event_loop.add_fd(
+            fd,
+            Interest::READABLE,
+            move |state, _, readiness: Readiness| {
+                // Failures disconnect the client, removing this source.
+                if readiness.writable && get(state).flush(id).is_err() {
+                    return;
+                }
+                if !(readiness.readable || readiness.hangup || readiness.error)
+                    || get(state).read(id).is_err()
+                {
+                    return;
+                }
+                // One at a time, the handler creates the resources of the new ids.
+                while let Ok(Some(request)) = get(state).next_request(id) {
+                    handler(state, id, request);
+                }
+            },
+        )
+    }
+
+    fn update(&self, source: SourceId, interest: Interest) -> io::Result<()> {
+        self.event_loop.update_fd(source, interest)
+    }
+
+    fn remove(&self, source: SourceId) {
+        self.event_loop.remove(source);
+    }
+}
+
+fn is_visible(filter: &Option<GlobalFilter>, client: &Client, global: &Global) -> bool {
+    filter.as_ref().is_none_or(|filter| filter(client, global))
+}
+
+fn send_global(client: &mut Client, global: &Global) {
+    for registry in client.registries.clone() {
+        client
+            .send_event(
+                registry,
+                REGISTRY_GLOBAL_OPCODE,
+                &[
+                    Argument::Uint(global.name),
+                    Argument::String(Some(global.interface.name)),
+                    Argument::Uint(global.version),
+                ],
+            )
+            .expect("no fds to duplicate");
+    }
+}
+
+fn runtime_dir() -> Result<PathBuf, BindError> {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .ok_or(BindError::NoXdgRuntimeDir)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io::{ErrorKind, IoSlice, Read, Write};
+    use std::os::fd::AsRawFd;
+    use std::os::unix::net::{SocketAncillary, UnixListener, UnixStream};
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    use wayland_client_protocol::wl::{self, prelude::*};
+    use wayland_server_core::event_loop::EventLoop;
+    use wayland_server_core::interface::{Interface, Message};
+    use wayland_server_core::wire::{Argument, Value, encode};
+
+    use super::{BindError, Server};
+    use crate::client::{Client, ClientId, Limits};
+    use crate::protocol::{
+        DISPLAY_DELETE_ID_OPCODE, DISPLAY_ERROR_INVALID_OBJECT, DISPLAY_ERROR_NO_MEMORY, DISPLAY_ID,
+    };
+    use crate::testing;
+
+    static COMPOSITOR: Interface = Interface {
+        name: "wl_compositor",
+        version: 6,
+        requests: &[
+            Message {
+                name: "create_surface",
+                signature: "n",
+                types: &[Some(&SURFACE)],
+            },
+            Message {
+                name: "create_region",
+                signature: "n",
+                types: &[Some(&REGION)],
+            },
+        ],
+        events: &[],
+    };
+
+    static SURFACE: Interface = Interface {
+        name: "wl_surface",
+        version: 6,
+        requests: &[
+            Message {
+                name: "set_input_region",
+                signature: "?o",
+                types: &[Some(&REGION)],
+            },
+            Message {
+                name: "offset",
+                signature: "5ii",
+                types: &[None, None],
+            },
+        ],
+        events: &[],
+    };
+
+    static REGION: Interface = Interface {
+        name: "wl_region",
+        version: 1,
+        requests: &[],
+        events: &[],
+    };
+
+    static SCREENCOPY: Interface = Interface {
+        name: "zwlr_screencopy_manager_v1",
+        version: 3,
+        requests: &[],
+        events: &[],
+    };
+
+    #[test]
+    fn accept() {
+        let path = std::env::temp_dir().join(format!("wayland-accept-{}", std::process::id()));
+        let mut server = Server::new();
+        server
+            .add_socket_fd(UnixListener::bind(&path).unwrap().into())
+            .unwrap();
+        assert!(server.accept().is_empty());
+
+        let mut first = UnixStream::connect(&path).unwrap();
+        let second = UnixStream::connect(&path).unwrap();
+        let clients = server.accept();
+        assert_eq!(2, clients.len());
+        assert_ne!(clients[0], clients[1]);
+        let client = server.get_client(clients[0]).unwrap();
+        assert_eq!(std::process::id() as i32, client.get_pid());
+        assert_eq!(unsafe { libc::getuid() }, client.get_uid());
+
+        first.write_all(&[0; 12]).unwrap();
+        server.read(clients[0]).unwrap();
+        server.read(clients[1]).unwrap();
+        assert_eq!(
+            12,
+            server.get_client(clients[0]).unwrap().incoming.bytes.len()
+        );
+
+        drop(second);
+        let error = server.read(clients[1]).unwrap_err();
+        assert_eq!(ErrorKind::UnexpectedEof, error.kind());
+        assert!(server.get_client(clients[1]).is_none());
+        assert_eq!(1, server.get_clients().count());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn add_socket() {
+        let path = std::env::temp_dir().join(format!("wayland-named-{}", std::process::id()));
+        let name = path.to_str().unwrap();
+        let mut server = Server::new();
+        server.add_socket(name).unwrap();
+        let error = Server::new().add_socket(name).unwrap_err();
+        assert!(matches!(error, BindError::InUse(_)));
+
+        let _stream = UnixStream::connect(&path).unwrap();
+        assert_eq!(1, server.accept().len());
+
+        drop(server);
+        assert!(!path.exists());
+        Server::new().add_socket(name).unwrap();
+    }
+
+    #[test]
+    fn add_socket_fd() {
+        let path = std::env::temp_dir().join(format!("wayland-adopted-{}", std::process::id()));
+        let mut server = Server::new();
+        server
+            .add_socket_fd(UnixListener::bind(&path).unwrap().into())
+            .unwrap();
+
+        let mut stream = UnixStream::connect(&path).unwrap();
+        let clients = server.accept();
+        assert_eq!(1, clients.len());
+        stream.write_all(&[0; 8]).unwrap();
+        server.read(clients[0]).unwrap();
+        assert_eq!(
+            8,
+            server.get_client(clients[0]).unwrap().incoming.bytes.len()
+        );
+
+        // Whoever created the socket removes it.
+        drop(server);
+        assert!(path.exists());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn globals() {
+        let (stream, mut peer) = UnixStream::pair().unwrap();
+        let mut server = Server::new();
+        let client = ClientId::new(0);
+        server
+            .clients
+            .insert(client, Client::new(client, stream).unwrap());
+
+        let bound = Rc::new(RefCell::new(Vec::new()));
+        let compositor = {
+            let bound = bound.clone();
+            server.create_global(&COMPOSITOR, 6, move |_, id, version| {
+                bound.borrow_mut().push((id, version))
+            })
+        };
+        let screencopy = server.create_global(&SCREENCOPY, 3, |_, _, _| {});
+        server.set_global_filter(|_, global| !global.get_interface().name.starts_with("zwlr_"));
+
+        let mut requests = Vec::new();
+        encode(1, 1, &[Argument::NewId(2)], &mut requests, &mut Vec::new()).unwrap();
+        encode(1, 0, &[Argument::NewId(3)], &mut requests, &mut Vec::new()).unwrap();
+        let bind = [
+            Argument::Uint(compositor),
+            Argument::String(Some("wl_compositor")),
+            Argument::Uint(5),
+            Argument::NewId(4),
+        ];
+        encode(2, 0, &bind, &mut requests, &mut Vec::new()).unwrap();
+        peer.write_all(&requests).unwrap();
+        server.read(client).unwrap();
+        assert!(server.dispatch(client).unwrap().is_empty());
+        assert_eq!(vec![(4, 5)], *bound.borrow());
+        // Only the compositor is advertised, then `wl_callback.done` and
+        // `wl_display.delete_id`.
+        let outgoing = &server.get_client(client).unwrap().outgoing.bytes;
+        assert_eq!(36 + 12 + 12, outgoing.len());
+
+        server.remove_global(screencopy);
+        server.remove_global(compositor);
+        let outgoing = &server.get_client(client).unwrap().outgoing.bytes;
+        assert_eq!(36 + 12 + 12 + 12, outgoing.len());
+
+        let mut requests = Vec::new();
+        let bind = [
+            Argument::Uint(screencopy),
+            Argument::String(Some("zwlr_screencopy_manager_v1")),
+            Argument::Uint(3),
+            Argument::NewId(5),
+        ];
+        encode(2, 0, &bind, &mut requests, &mut Vec::new()).unwrap();
+        peer.write_all(&requests).unwrap();
+        server.read(client).unwrap();
+        let Err(error) = server.dispatch(client) else {
+            panic!("the request was accepted");
+        };
+        assert_eq!(
+            "invalid global zwlr_screencopy_manager_v1 (2)",
+            error.message
+        );
+
+        // A client that did not see the removal yet can still bind the compositor, but
+        // a new registry is not told about it.
+        let (stream, mut peer) = UnixStream::pair().unwrap();
+        let client = ClientId::new(1);
+        server
+            .clients
+            .insert(client, Client::new(client, stream).unwrap());
+        let mut requests = Vec::new();
+        encode(1, 1, &[Argument::NewId(2)], &mut requests, &mut Vec::new()).unwrap();
+        let bind = [
+            Argument::Uint(compositor),
+            Argument::String(Some("wl_compositor")),
+            Argument::Uint(6),
+            Argument::NewId(3),
+        ];
+        encode(2, 0, &bind, &mut requests, &mut Vec::new()).unwrap();
+        peer.write_all(&requests).unwrap();
+        server.read(client).unwrap();
+        assert!(server.dispatch(client).unwrap().is_empty());
+        assert_eq!(vec![(4, 5), (3, 6)], *bound.borrow());
+        assert!(server.get_client(client).unwrap().outgoing.bytes.is_empty());
+
+        server.destroy_global(compositor);
+        let bind = [
+            Argument::Uint(compositor),
+            Argument::String(Some("wl_compositor")),
+            Argument::Uint(6),
+            Argument::NewId(4),
+        ];
+        let mut requests = Vec::new();
+        encode(2, 0, &bind, &mut requests, &mut Vec::new()).unwrap();
+        peer.write_all(&requests).unwrap();
+        server.read(client).unwrap();
+        let Err(error) = server.dispatch(client) else {
+            panic!("the request was accepted");
+        };
+        assert_eq!("invalid global wl_compositor (1)", error.message);
+    }
+
+    #[test]
+    #[should_panic(expected = "`wl_compositor` has no version 7")]
+    fn global_version() {
+        Server::new().create_global(&COMPOSITOR, 7, |_, _, _| {});
+    }
+
+    #[test]
+    fn protocol_errors() {
+        let (stream, mut peer) = UnixStream::pair().unwrap();
+        let mut server = Server::new();
+        let client = ClientId::new(0);
+        server
+            .clients
+            .insert(client, Client::new(client, stream).unwrap());
+
+        // `wl_display.get_registry` with an id past the next free one.
+        let request = [1_u32, (12 << 16) | 1, 5];
+        peer.write_all(&request.map(u32::to_ne_bytes).concat())
+            .unwrap();
+        server.read(client).unwrap();
+        let Err(error) = server.dispatch(client) else {
+            panic!("the request was accepted");
+        };
+        assert_eq!("invalid new id 5", error.message);
+        assert!(server.get_client(client).is_none());
+
+        let mut bytes = Vec::new();
+        peer.read_to_end(&mut bytes).unwrap();
+        let words = bytes
+            .chunks(4)
+            .map(|w| u32::from_ne_bytes(w.try_into().unwrap()))
+            .collect::<Vec<u32>>();
+        // `wl_display.error` about `wl_display`, with code `invalid_object`.
+        assert_eq!(
+            [1, 0, 1, 0],
+            [words[0], words[1] & 0xFFFF, words[2], words[3]]
+        );
+    }
+
+    #[test]
+    fn object_types() {
+        let (stream, mut peer) = UnixStream::pair().unwrap();
+        let mut server = Server::new();
+        let client = ClientId::new(0);
+        let mut resources = Client::new(client, stream).unwrap();
+        resources.insert_resource(2, &SURFACE, 6).unwrap();
+        resources.insert_resource(3, &REGION, 1).unwrap();
+        server.clients.insert(client, resources);
+
+        let mut requests = Vec::new();
+        encode(
+            2,
+            0,
+            &[Argument::Object(Some(3))],
+            &mut requests,
+            &mut Vec::new(),
+        )
+        .unwrap();
+        encode(
+            2,
+            0,
+            &[Argument::Object(None)],
+            &mut requests,
+            &mut Vec::new(),
+        )
+        .unwrap();
+        peer.write_all(&requests).unwrap();
+        server.read(client).unwrap();
+        assert_eq!(2, server.dispatch(client).unwrap().len());
+
+        // The surface itself is no region.
+        let mut requests = Vec::new();
+        encode(
+            2,
+            0,
+            &[Argument::Object(Some(2))],
+            &mut requests,
+            &mut Vec::new(),
+        )
+        .unwrap();
+        peer.write_all(&requests).unwrap();
+        server.read(client).unwrap();
+        let Err(error) = server.dispatch(client) else {
+            panic!("the request was accepted");
+        };
+        assert_eq!(
+            "invalid object wl_surface@2, expected wl_region, message wl_surface@2.set_input_region",
+            error.message
+        );
+        assert_eq!(DISPLAY_ERROR_INVALID_OBJECT, error.code);
+    }
+
+    #[test]
+    fn method_since() {
+        let (stream, mut peer) = UnixStream::pair().unwrap();
+        let mut server = Server::new();
+        let client = ClientId::new(0);
+        let mut resources = Client::new(client, stream).unwrap();
+        resources.insert_resource(2, &SURFACE, 4).unwrap();
+        server.clients.insert(client, resources);
+
+        let mut requests = Vec::new();
+        let offset = [Argument::Int(1), Argument::Int(2)];
+        encode(2, 1, &offset, &mut requests, &mut Vec::new()).unwrap();
+        peer.write_all(&requests).unwrap();
+        server.read(client).unwrap();
+        let Err(error) = server.dispatch(client) else {
+            panic!("the request was accepted");
+        };
+        assert_eq!(
+            "invalid method 1 (since 5 > 4), object wl_surface@2",
+            error.message
+        );
+    }
+
+    #[test]
+    fn client_command() {
+        let mut server = Server::new();
+        let (id, mut command) = server.create_client_command("sh").unwrap();
+        let status = command
+            .args(["-c", "test -S /proc/self/fd/$WAYLAND_SOCKET"])
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        // The only other end was in the command and the child, both are gone.
+        drop(command);
+        let error = server.read(id).unwrap_err();
+        assert_eq!(ErrorKind::UnexpectedEof, error.kind());
+        assert!(server.get_client(id).is_none());
+    }
+
+    #[test]
+    fn event_loop() {
+        struct State {
+            server: Server,
+            requests: Vec<(ClientId, u16)>,
+        }
+
+        struct Callback;
+
+        impl wl::callback::Dispatch<Option<u32>> for Callback {
+            fn done(&mut self, state: &mut Option<u32>, event: wl::callback::DoneEvent) {
+                *state = Some(event.callback_data);
+            }
+        }
+
+        let event_loop = EventLoop::<State>::new().unwrap();
+        let mut state = State {
+            server: Server::new(),
+            requests: Vec::new(),
+        };
+        let name = state.server.create_global(&COMPOSITOR, 6, |_, _, _| {});
+        state
+            .server
+            .insert_into(
+                &event_loop,
+                |state| &mut state.server,
+                |state, id, request| {
+                    let [Value::NewId(surface)] = request.args[..] else {
+                        unreachable!()
+                    };
+                    let client = state.server.get_client_mut(id).unwrap();
+                    client.insert_resource(surface, &SURFACE, 6).unwrap();
+                    state.requests.push((id, request.opcode));
+                },
+            )
+            .unwrap();
+
+        // Added after `insert_into`, still polled.
+        let (id, conn) = testing::connect(&mut state.server).unwrap();
+        let display = conn.get_display::<wl::Display, 1>();
+        let compositor = display.get_registry().bind::<wl::Compositor, 6>(name);
+        compositor.create_surface();
+        compositor.create_surface();
+        display.sync().set_handler(Callback);
+        conn.flush().unwrap();
+
+        event_loop
+            .dispatch(&mut state, Some(Duration::ZERO))
+            .unwrap();
+        assert_eq!(vec![(id, 0), (id, 0)], state.requests);
+
+        // The callback is flushed before the loop sleeps.
+        event_loop
+            .dispatch(&mut state, Some(Duration::ZERO))
+            .unwrap();
+        let mut serial = None::<u32>;
+        while serial.is_none() {
+            conn.blocking_dispatch(&mut serial).unwrap();
+        }
+        // `get_registry` takes none.
+        assert_eq!(Some(1), serial);
+
+        drop((display, compositor, conn));
+        event_loop
+            .dispatch(&mut state, Some(Duration::ZERO))
+            .unwrap();
+        assert!(state.server.get_client(id).is_none());
+    }
+
+    #[test]
+    fn limits() {
+        let mut server = Server::new();
+        server.set_limits(Limits {
+            max_objects: 2,
+            max_incoming_size: 64,
+            max_outgoing_size: 4096,
+            ..Limits::default()
+        });
+
+        // Two `wl_display.get_registry`, the second one past the limit.
+        let (stream, mut peer) = UnixStream::pair().unwrap();
+        let client = server.create_client(stream).unwrap();
+        let requests = [1_u32, (12 << 16) | 1, 2, 1, (12 << 16) | 1, 3];
+        peer.write_all(&requests.map(u32::to_ne_bytes).concat())
+            .unwrap();
+        server.read(client).unwrap();
+        let Err(error) = server.dispatch(client) else {
+            panic!("the requests were accepted");
+        };
+        assert_eq!(
+            "too many objects, message wl_display@1.get_registry",
+            error.message
+        );
+
+        let (stream, mut peer) = UnixStream::pair().unwrap();
+        let client = server.create_client(stream).unwrap();
+        peer.write_all(&[1_u32, (128 << 16) | 1].map(u32::to_ne_bytes).concat())
+            .unwrap();
+        server.read(client).unwrap();
+        let Err(error) = server.dispatch(client) else {
+            panic!("the request was accepted");
+        };
+        assert_eq!("message size 128 over the limit", error.message);
+
+        // `wl_shm.create_pool` with its fd, with no fds allowed.
+        static SHM: Interface = Interface {
+            name: "wl_shm",
+            version: 1,
+            requests: &[Message {
+                name: "create_pool",
+                signature: "nhi",
+                types: &[None, None, None],
+            }],
+            events: &[],
+        };
+        server.set_limits(Limits {
+            max_message_fds: 0,
+            ..Limits::default()
+        });
+        let (stream, peer) = UnixStream::pair().unwrap();
+        let client = server.create_client(stream).unwrap();
+        let resource = server.get_client_mut(client).unwrap();
+        resource.insert_resource(2, &SHM, 1).unwrap();
+        let request = [2_u32, 16 << 16, 3, 4096].map(u32::to_ne_bytes).concat();
+        let mut buffer = [0; 64];
+        let mut ancillary = SocketAncillary::new(&mut buffer);
+        ancillary.add_fds(&[peer.as_raw_fd()]);
+        peer.send_vectored_with_ancillary(&[IoSlice::new(&request)], &mut ancillary)
+            .unwrap();
+        server.read(client).unwrap();
+        let Err(error) = server.dispatch(client) else {
+            panic!("the request was accepted");
+        };
+        assert_eq!(
+            "too many fds (1), message wl_shm@2.create_pool",
+            error.message
+        );
+        assert_eq!(DISPLAY_ERROR_NO_MEMORY, error.code);
+
+        // The peer reads nothing, the socket and then the queue fill up.
+        let (stream, _peer) = UnixStream::pair().unwrap();
+        let id = server.create_client(stream).unwrap();
+        let client = server.get_client_mut(id).unwrap();
+        for _ in 0..1_000_000 {
+            if client.is_errored() {
+                break;
+            }
+            client
+                .send_event(DISPLAY_ID, DISPLAY_DELETE_ID_OPCODE, &[Argument::Uint(2)])
+                .unwrap();
+        }
+        assert!(client.is_errored());
+        assert!(client.outgoing.bytes.is_empty());
+        assert!(server.read(id).is_err());
+        assert!(server.get_client(id).is_none());
     }
 }
@@ -0,0 +1,164 @@
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io;
+use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+
+// The names tried by `Socket::auto`, like libwayland.
+const MAX_DISPLAY_NUMBER: u32 = 32;
+
+// A listening socket. Those bound by the server are removed from the file system on
+// drop, adopted ones are left to whoever created them.
+pub(crate) struct Socket {
+    listener: UnixListener,
+    path: Option<PathBuf>,
+    // Held as long as the socket lives, it tells other servers the name is taken.
+    _lock: Option<Lock>,
+}
+
+struct Lock {
+    _file: File,
+    path: PathBuf,
+}
+
+impl Socket {
+    // Takes the first free name from `wayland-0` to `wayland-32` in `runtime_dir`.
+    pub(crate) fn auto(runtime_dir: &Path) -> Result<Self, BindError> {
+        for number in 0..=MAX_DISPLAY_NUMBER {
+            match Self::bind(runtime_dir.join(format!("wayland-{number}"))) {
+                Err(BindError::InUse(_)) => continue,
+                result => return result,
+            }
+        }
+        Err(BindError::NoFreeName)
+    }
+
+    // A socket left behind by a server that is gone, i.e. does not hold the lock
+    // anymore, is replaced.
+    pub(crate) fn bind(path: PathBuf) -> Result<Self, BindError> {
+        let lock = Lock::acquire(path.with_added_extension("lock"))?;
+
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(BindError::Io(e)),
+            _ => {}
+        }
+
+        let listener = UnixListener::bind(&path).map_err(BindError::Io)?;
+        listener.set_nonblocking(true).map_err(BindError::Io)?;
+        Ok(Self {
+            listener,
+            path: Some(path),
+            _lock: Some(lock),
+        })
+    }
+
+    // For a socket that is already bound and listening, e.g. passed by systemd.
+    pub(crate) fn from_fd(fd: OwnedFd) -> Result<Self, BindError> {
+        let listener = UnixListener::from(fd);
+        listener.set_nonblocking(true).map_err(BindError::Io)?;
+        Ok(Self {
+            listener,
+            path: None,
+            _lock: None,
+        })
+    }
+
+    // `None` once every pending connection was accepted.
+    pub(crate) fn accept(&self) -> io::Result<Option<UnixStream>> {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => return Ok(Some(stream)),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub(crate) fn get_name(&self) -> Option<&str> {
+        self.path.as_ref()?.file_name()?.to_str()
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        if let Some(path) = &self.path {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+impl AsFd for Socket {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.listener.as_fd()
+    }
+}
+
+impl Lock {
+    fn acquire(path: PathBuf) -> Result<Self, BindError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .mode(0o660)
+            .open(&path)
+            .map_err(BindError::Io)?;
+
+        match file.try_lock() {
+            Ok(()) => Ok(Self { _file: file, path }),
+            Err(TryLockError::WouldBlock) => Err(BindError::InUse(path)),
+            Err(TryLockError::Error(e)) => Err(BindError::Io(e)),
+        }
+    }
+}
+
+// Removed before the file is closed, so that no other server locks a file that is
+// about to disappear.
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[derive(Debug)]
+pub enum BindError {
+    NoXdgRuntimeDir,
+    // Another server holds the lock of this socket.
+    InUse(PathBuf),
+    // Every name from `wayland-0` to `wayland-32` is in use.
+    NoFreeName,
+    Io(io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::Socket;
+
+    #[test]
+    fn auto() {
+        let runtime_dir =
+            std::env::temp_dir().join(format!("wayland-server-{}", std::process::id()));
+        fs::create_dir_all(&runtime_dir).unwrap();
+        // Stale, nobody holds its lock.
+        fs::write(runtime_dir.join("wayland-1"), "").unwrap();
+
+        let first = Socket::auto(&runtime_dir).unwrap();
+        let second = Socket::auto(&runtime_dir).unwrap();
+        assert_eq!(Some("wayland-0"), first.get_name());
+        assert_eq!(Some("wayland-1"), second.get_name());
+
+        drop(first);
+        assert!(!runtime_dir.join("wayland-0").exists());
+        assert!(!runtime_dir.join("wayland-0.lock").exists());
+        assert_eq!(
+            Some("wayland-0"),
+            Socket::auto(&runtime_dir).unwrap().get_name()
+        );
+
+        drop(second);
+        fs::remove_dir_all(&runtime_dir).unwrap();
+    }
+}
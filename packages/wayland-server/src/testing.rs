@@ -0,0 +1,81 @@
+use std::io;
+use std::os::unix::net::UnixStream;
+
+use wayland_client_core::connection::Connection;
+
+use crate::client::ClientId;
+use crate::server::Server;
+
+// A client of the same process, joined to the server by a socketpair. Nothing is bound
+// in the file system, so no `XDG_RUNTIME_DIR` is needed.
+pub fn connect(server: &mut Server) -> io::Result<(ClientId, Connection)> {
+    let (server_end, client_end) = UnixStream::pair()?;
+    let id = server.create_client(server_end)?;
+    Ok((id, Connection::from_fd(client_end.into())))
+}
+
+#[cfg(test)]
+mod tests {
+    use wayland_client_protocol::wl::{self, prelude::*};
+    use wayland_server_core::interface::Interface;
+
+    use super::connect;
+    use crate::server::Server;
+
+    static COMPOSITOR: Interface = Interface {
+        name: "wl_compositor",
+        version: 6,
+        requests: &[],
+        events: &[],
+    };
+
+    #[derive(Default)]
+    struct App {
+        globals: Vec<(u32, String, u32)>,
+        done: bool,
+    }
+
+    struct Registry;
+
+    impl wl::registry::Dispatch<App> for Registry {
+        fn global(&mut self, state: &mut App, event: wl::registry::GlobalEvent) {
+            state
+                .globals
+                .push((event.name, event.interface, event.version));
+        }
+    }
+
+    struct Callback;
+
+    impl wl::callback::Dispatch<App> for Callback {
+        fn done(&mut self, state: &mut App, _: wl::callback::DoneEvent) {
+            state.done = true;
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut server = Server::new();
+        let name = server.create_global(&COMPOSITOR, 4, |_, _, _| {});
+        let (id, conn) = connect(&mut server).unwrap();
+        assert_eq!(
+            std::process::id() as i32,
+            server.get_client(id).unwrap().get_pid()
+        );
+
+        let display = conn.get_display::<wl::Display, 1>();
+        display.get_registry().set_handler(Registry);
+        display.sync().set_handler(Callback);
+        conn.flush().unwrap();
+
+        server.read(id).unwrap();
+        assert!(server.dispatch(id).unwrap().is_empty());
+        server.flush(id).unwrap();
+
+        let mut app = App::default();
+        while !app.done {
+            conn.blocking_dispatch(&mut app).unwrap();
+        }
+        assert_eq!(vec![(name, "wl_compositor".to_string(), 4)], app.globals);
+    }
+}